@@ -1,93 +1,509 @@
 use regex::Regex;
 use rustvent2022::get_input;
+use std::cmp::Reverse;
+use std::collections::{BinaryHeap, HashMap};
+use std::env;
+use std::error::Error;
+use std::fmt;
+use std::fs;
+use std::rc::Rc;
 use std::str::FromStr;
 
 type Input = (Vec<Vec<char>>, Vec<(usize, usize, usize)>);
-fn parse(input: &str) -> Input {
-    let mut spl = input.split("\n\n");
-
-    let st = spl.next().unwrap();
-    let mut lines = st.lines().rev();
-    let n_stacks = lines.next().unwrap().replace(" ", "").len();
-    let mut stacks = vec![Vec::<char>::new(); n_stacks];
-
-    for line in lines {
-        let mut chars = line.chars();
-        chars.next().unwrap();
-        for i in 0..n_stacks {
-            match chars.next() {
-                Some(' ') => (),
-                Some(c) => stacks[i].push(c),
-                None => panic!("Line in input too short!"),
+
+#[derive(Debug, PartialEq)]
+struct ParseError {
+    /// Counting from 1, like the column.
+    line: usize,
+    column: usize,
+    message: &'static str,
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "line {}, column {}: {}",
+            self.line, self.column, self.message
+        )
+    }
+}
+
+impl Error for ParseError {}
+
+/// Parses the drawing of the stacks and the moves below it, separated by a
+/// blank line. The label line below the drawing tells where the stacks are:
+/// a crate belongs to the label its brackets overlap, so labels may have
+/// several digits and lines may lack trailing spaces.
+fn parse(input: &str) -> Result<Input, ParseError> {
+    let error = |line, column, message| ParseError {
+        line,
+        column,
+        message,
+    };
+    let lines: Vec<&str> = input.lines().collect();
+    let blank = lines
+        .iter()
+        .position(|l| l.trim().is_empty())
+        .unwrap_or(lines.len());
+    let (label_line, crate_lines) =
+        lines[..blank]
+            .split_last()
+            .ok_or(error(1, 1, "no stack labels"))?;
+
+    // columns covered by each label
+    let mut labels: Vec<(usize, usize)> = Vec::new();
+    let mut start = None;
+    for (i, c) in label_line.chars().chain([' ']).enumerate() {
+        match (c.is_whitespace(), start) {
+            (true, Some(s)) => {
+                let label: String = label_line.chars().skip(s).take(i - s).collect();
+                if label.parse() != Ok(labels.len() + 1) {
+                    return Err(error(blank, s + 1, "stack labels must count up from 1"));
+                }
+                labels.push((s, i));
+                start = None;
             }
-            for _ in 0..3 {
-                chars.next();
+            (false, None) => start = Some(i),
+            _ => (),
+        }
+    }
+
+    let mut stacks = vec![Vec::<char>::new(); labels.len()];
+    for (level, (n, line)) in crate_lines.iter().enumerate().rev().enumerate() {
+        let chars: Vec<char> = line.chars().collect();
+        let mut i = 0;
+        while i < chars.len() {
+            if chars[i] == ' ' {
+                i += 1;
+                continue;
+            }
+            let error = |message| error(n + 1, i + 1, message);
+            if chars[i] != '[' || chars.get(i + 2) != Some(&']') {
+                return Err(error("expected a crate like '[A]'"));
             }
+            let stack = labels
+                .iter()
+                .position(|&(start, end)| start <= i + 2 && i < end)
+                .ok_or(error("crate not above a stack label"))?;
+            if stacks[stack].len() != level {
+                return Err(error("crate without a crate below"));
+            }
+            stacks[stack].push(chars[i + 1]);
+            i += 3;
         }
     }
 
-    let re_inst = Regex::new(r"move (\d+) from (\d+) to (\d+)").unwrap();
-    let inst = spl.next().unwrap();
-    let instructions: Vec<(usize, usize, usize)> = inst
-        .lines()
-        .map(|l| {
-            let cap = re_inst.captures_iter(l).next().unwrap();
-            (
-                usize::from_str(&cap[1]).unwrap(),
-                usize::from_str(&cap[2]).unwrap(),
-                usize::from_str(&cap[3]).unwrap(),
-            )
-        })
-        .collect();
+    let re_inst = Regex::new(r"^move (\d+) from (\d+) to (\d+)$").unwrap();
+    let mut instructions = Vec::new();
+    for (n, line) in lines.iter().enumerate().skip(blank) {
+        if line.trim().is_empty() {
+            continue;
+        }
+        let cap = re_inst.captures(line.trim()).ok_or(error(
+            n + 1,
+            1,
+            "expected 'move N from A to B'",
+        ))?;
+        let number = |i| usize::from_str(&cap[i]).or(Err(error(n + 1, 1, "number too large")));
+        instructions.push((number(1)?, number(2)?, number(3)?));
+    }
 
-    (stacks, instructions)
+    Ok((stacks, instructions))
 }
 
-fn part_one(instructions: &Vec<(usize, usize, usize)>, stacks: &mut Vec<Vec<char>>) -> String {
+/// Draws the stacks like the puzzle does, so that `parse` reads them back.
+fn draw(stacks: &[Vec<char>]) -> String {
+    let height = stacks.iter().map(Vec::len).max().unwrap_or(0);
+    let mut lines = Vec::new();
+    for level in (0..height).rev() {
+        let cells: Vec<String> = stacks
+            .iter()
+            .map(|stack| match stack.get(level) {
+                Some(c) => format!("[{c}]"),
+                None => "   ".to_string(),
+            })
+            .collect();
+        lines.push(cells.join(" "));
+    }
+    let labels: Vec<String> = (1..=stacks.len()).map(|i| format!("{i:^3}")).collect();
+    lines.push(labels.join(" "));
+    lines.join("\n") + "\n"
+}
+
+/// The whole puzzle input for the given stacks and moves.
+fn render(stacks: &[Vec<char>], instructions: &[(usize, usize, usize)]) -> String {
+    let mut out = draw(stacks);
+    out.push('\n');
     for (n, from, to) in instructions {
-        for _ in 0..*n {
-            let o = stacks[*from - 1].pop().unwrap();
-            stacks[*to - 1].push(o);
+        out.push_str(&format!("move {n} from {from} to {to}\n"));
+    }
+    out
+}
+
+/// Moves crates between stacks, lifting a number of crates off the top at once
+/// and putting them down in the same order.
+trait Crane {
+    /// How many crates each lift takes when moving `n` crates.
+    fn lifts(&self, n: usize) -> Vec<usize>;
+}
+
+/// Lifts one crate at a time.
+struct CrateMover9000;
+
+/// Lifts all crates of a move at once.
+struct CrateMover9001;
+
+/// Lifts up to `k` crates at once, where a `k` of 0 is taken as 1.
+struct Capacity(usize);
+
+impl Crane for CrateMover9000 {
+    fn lifts(&self, n: usize) -> Vec<usize> {
+        vec![1; n]
+    }
+}
+
+impl Crane for CrateMover9001 {
+    fn lifts(&self, n: usize) -> Vec<usize> {
+        if n == 0 {
+            Vec::new()
+        } else {
+            vec![n]
         }
     }
+}
 
-    let mut s = String::with_capacity(stacks.len());
-    for stack in stacks {
-        match stack.last() {
-            Some(c) => s.push(*c),
-            None => (),
+impl Crane for Capacity {
+    fn lifts(&self, n: usize) -> Vec<usize> {
+        let k = self.0.max(1);
+        let mut lifts = vec![k; n / k];
+        if !n.is_multiple_of(k) {
+            lifts.push(n % k);
         }
+        lifts
     }
-    s
 }
 
-fn part_two(instructions: &Vec<(usize, usize, usize)>, stacks: &mut Vec<Vec<char>>) -> String {
-    for (n, from, to) in instructions {
-        let len = stacks[*from - 1].len();
-        let mut tail = stacks[*from - 1].split_off(len.saturating_sub(*n));
-        stacks[*to - 1].append(&mut tail);
+#[derive(Debug, PartialEq)]
+enum MoveError {
+    NoSuchStack {
+        step: usize,
+        stack: usize,
+    },
+    NotEnoughCrates {
+        step: usize,
+        stack: usize,
+        wanted: usize,
+        available: usize,
+    },
+}
+
+impl fmt::Display for MoveError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            MoveError::NoSuchStack { step, stack } => write!(f, "move {step}: no stack {stack}"),
+            MoveError::NotEnoughCrates {
+                step,
+                stack,
+                wanted,
+                available,
+            } => write!(
+                f,
+                "move {step}: can't take {wanted} crates from stack {stack} holding {available}"
+            ),
+        }
     }
+}
+
+impl Error for MoveError {}
+
+/// Runs the instructions with the given crane and returns the number of
+/// lifts. Every move is checked before any crate is moved, so on error the
+/// stacks are left as they were after the previous move.
+fn simulate(
+    crane: &(impl Crane + ?Sized),
+    instructions: &[(usize, usize, usize)],
+    stacks: &mut [Vec<char>],
+) -> Result<usize, MoveError> {
+    let mut lifts = 0;
+    for (i, &(n, from, to)) in instructions.iter().enumerate() {
+        let step = i + 1;
+        for stack in [from, to] {
+            if stack == 0 || stack > stacks.len() {
+                return Err(MoveError::NoSuchStack { step, stack });
+            }
+        }
+        let available = stacks[from - 1].len();
+        if available < n {
+            return Err(MoveError::NotEnoughCrates {
+                step,
+                stack: from,
+                wanted: n,
+                available,
+            });
+        }
 
-    let mut s = String::with_capacity(stacks.len());
-    for stack in stacks {
-        match stack.last() {
-            Some(c) => s.push(*c),
-            None => (),
+        for lift in crane.lifts(n) {
+            let len = stacks[from - 1].len();
+            let mut crates = stacks[from - 1].split_off(len - lift);
+            stacks[to - 1].append(&mut crates);
+            lifts += 1;
         }
     }
-    s
+    Ok(lifts)
+}
+
+fn tops(stacks: &[Vec<char>]) -> String {
+    stacks.iter().filter_map(|stack| stack.last()).collect()
+}
+
+/// What a plan has to achieve: the crates on top of the stacks as `tops`
+/// gives them, or exactly the given stacks.
+enum Goal {
+    Tops(String),
+    Stacks(Vec<Vec<char>>),
+}
+
+impl Goal {
+    fn is_reached(&self, stacks: &[Vec<char>]) -> bool {
+        match self {
+            Goal::Tops(target) => tops(stacks) == *target,
+            Goal::Stacks(target) => stacks == target,
+        }
+    }
+
+    /// Whether the goal can be reached at all from `stacks`, which never
+    /// gain or lose crates.
+    fn is_possible(&self, stacks: &[Vec<char>]) -> bool {
+        let mut crates: Vec<char> = stacks.iter().flatten().copied().collect();
+        crates.sort_unstable();
+        match self {
+            Goal::Tops(target) => {
+                let mut wanted: Vec<char> = target.chars().collect();
+                wanted.sort_unstable();
+                wanted.dedup();
+                target.chars().count() <= stacks.len()
+                    && wanted.iter().all(|c| {
+                        let have = crates.iter().filter(|&d| d == c).count();
+                        have >= target.chars().filter(|d| d == c).count()
+                    })
+            }
+            Goal::Stacks(target) => {
+                let mut wanted: Vec<char> = target.iter().flatten().copied().collect();
+                wanted.sort_unstable();
+                target.len() == stacks.len() && wanted == crates
+            }
+        }
+    }
+
+    /// How many stacks still look wrong, to steer the search.
+    fn distance(&self, stacks: &[Vec<char>]) -> usize {
+        match self {
+            Goal::Tops(target) => {
+                let tops: Vec<char> = tops(stacks).chars().collect();
+                let target: Vec<char> = target.chars().collect();
+                let wrong = tops.iter().zip(&target).filter(|(a, b)| a != b).count();
+                wrong + tops.len().abs_diff(target.len())
+            }
+            Goal::Stacks(target) => stacks.iter().zip(target).filter(|(a, b)| a != b).count(),
+        }
+    }
+}
+
+/// Finds moves reaching the goal with the given crane. This first searches
+/// for a short sequence, and if that finds nothing among `limit`
+/// arrangements, builds one for a goal of as many tops as stacks.
+fn plan(
+    crane: &(impl Crane + ?Sized),
+    stacks: &[Vec<char>],
+    goal: &Goal,
+    limit: usize,
+) -> Option<Vec<(usize, usize, usize)>> {
+    if !goal.is_possible(stacks) {
+        return None;
+    }
+    search(crane, stacks, goal, limit).or_else(|| {
+        let Goal::Tops(target) = goal else {
+            return None;
+        };
+        let moves = build_tops(stacks, &target.chars().collect::<Vec<_>>())?;
+        let mut built = stacks.to_vec();
+        simulate(crane, &moves, &mut built).ok()?;
+        goal.is_reached(&built).then_some(moves)
+    })
+}
+
+/// Searches best first, trying arrangements with fewer wrong stacks and
+/// fewer moves first. Every arrangement is kept once, with the one it was
+/// reached from and the move leading there. The result is short, but not
+/// always the shortest.
+fn search(
+    crane: &(impl Crane + ?Sized),
+    stacks: &[Vec<char>],
+    goal: &Goal,
+    limit: usize,
+) -> Option<Vec<(usize, usize, usize)>> {
+    type Arrangement = Rc<Vec<Vec<char>>>;
+    type Step = (Arrangement, (usize, usize, usize));
+
+    let start: Arrangement = Rc::new(stacks.to_vec());
+    let mut reached: HashMap<Arrangement, Option<Step>> = HashMap::from([(start.clone(), None)]);
+    // also ordered by when arrangements were found, to never compare them
+    let mut open = BinaryHeap::from([Reverse((goal.distance(&start), 0, 0, start))]);
+
+    while let Some(Reverse((_, moves, _, current))) = open.pop() {
+        if goal.is_reached(&current) {
+            let mut path = Vec::new();
+            let mut at = &current;
+            while let Some(Some((previous, step))) = reached.get(at) {
+                path.push(*step);
+                at = previous;
+            }
+            path.reverse();
+            return Some(path);
+        }
+
+        for from in 1..=current.len() {
+            for to in (1..=current.len()).filter(|&to| to != from) {
+                for n in 1..=current[from - 1].len() {
+                    let mut next = current.to_vec();
+                    if simulate(crane, &[(n, from, to)], &mut next).is_err() {
+                        continue;
+                    }
+                    if reached.contains_key(&next) {
+                        continue;
+                    }
+                    if reached.len() >= limit {
+                        return None;
+                    }
+                    let next = Rc::new(next);
+                    reached.insert(next.clone(), Some((current.clone(), (n, from, to))));
+                    let cost = moves + 1 + goal.distance(&next);
+                    open.push(Reverse((cost, moves + 1, reached.len(), next)));
+                }
+            }
+        }
+    }
+    None
+}
+
+/// Moves one crate at a time, which every crane does alike, so that the
+/// stacks get the `target` crates on top, one stack after the other. Crates
+/// in the way go to a stack not yet done, or for a while onto one that is.
+/// Fails with fewer than three stacks or without the crates needed.
+fn build_tops(stacks: &[Vec<char>], target: &[char]) -> Option<Vec<(usize, usize, usize)>> {
+    let n = stacks.len();
+    if target.len() != n || n < 3 {
+        return None;
+    }
+    let mut stacks = stacks.to_vec();
+    let mut moves = Vec::new();
+    let mut shift = |stacks: &mut Vec<Vec<char>>, from: usize, to: usize, count: usize| {
+        for _ in 0..count {
+            let c = stacks[from].pop()?;
+            stacks[to].push(c);
+            moves.push((1, from + 1, to + 1));
+        }
+        Some(())
+    };
+
+    for (i, &wanted) in target.iter().enumerate() {
+        // the crate with the fewest above it, but not a top already done
+        let (above, j) = (0..n)
+            .filter_map(|j| {
+                let stack = &stacks[j][..stacks[j].len() - usize::from(j < i)];
+                let at = stack.iter().rposition(|&c| c == wanted)?;
+                Some((stacks[j].len() - 1 - at, j))
+            })
+            .min_by_key(|&(above, j)| (above, j != i, j < i))?;
+        if j == i && above == 0 {
+            continue;
+        }
+        // rather a stack not done yet, else one to put back afterwards
+        let spare = (i + 1..n).chain(0..i).find(|&k| k != j)?;
+
+        if j != i {
+            shift(&mut stacks, j, spare, above)?;
+            shift(&mut stacks, j, i, 1)?;
+            if spare < i || j < i {
+                shift(&mut stacks, spare, j, above)?;
+            }
+        } else if spare > i {
+            shift(&mut stacks, i, spare, above)?;
+        } else {
+            // the last stack, with two done ones to hold everything
+            let other = (0..i).find(|&k| k != spare)?;
+            shift(&mut stacks, i, spare, above)?;
+            shift(&mut stacks, i, other, 1)?;
+            shift(&mut stacks, spare, i, above)?;
+            shift(&mut stacks, other, i, 1)?;
+        }
+    }
+    Some(moves)
+}
+
+fn part_one(
+    instructions: &[(usize, usize, usize)],
+    stacks: &mut [Vec<char>],
+) -> Result<String, MoveError> {
+    simulate(&CrateMover9000, instructions, stacks)?;
+    Ok(tops(stacks))
+}
+
+fn part_two(
+    instructions: &[(usize, usize, usize)],
+    stacks: &mut [Vec<char>],
+) -> Result<String, MoveError> {
+    simulate(&CrateMover9001, instructions, stacks)?;
+    Ok(tops(stacks))
 }
 
 fn main() -> Result<(), Box<dyn std::error::Error + 'static>> {
-    let (stacks, instructions) = parse(&get_input("2022", "5"));
+    let (stacks, instructions) = parse(&get_input("2022", "5"))?;
     println!(
         "Solution part one: {}",
-        part_one(&instructions, &mut stacks.clone())
+        part_one(&instructions, &mut stacks.clone())?
     );
     println!(
         "Solution part two: {}",
-        part_two(&instructions, &mut stacks.clone())
+        part_two(&instructions, &mut stacks.clone())?
     );
+
+    for arg in env::args().skip(1) {
+        if arg == "--render" {
+            print!("{}", render(&stacks, &instructions));
+        }
+        if arg == "--draw" {
+            let mut stacks = stacks.clone();
+            println!("{}", draw(&stacks));
+            for instruction @ (n, from, to) in &instructions {
+                simulate(&CrateMover9000, &[*instruction], &mut stacks)?;
+                println!("move {n} from {from} to {to}\n{}", draw(&stacks));
+            }
+        }
+        let goal = if let Some(target) = arg.strip_prefix("--plan=") {
+            Some(Goal::Tops(target.to_string()))
+        } else if let Some(path) = arg.strip_prefix("--plan-to=") {
+            Some(Goal::Stacks(parse(&fs::read_to_string(path)?)?.0))
+        } else {
+            None
+        };
+        if let Some(goal) = goal {
+            let cranes: [(&str, &dyn Crane); 2] =
+                [("9000", &CrateMover9000), ("9001", &CrateMover9001)];
+            for (name, crane) in cranes {
+                match plan(crane, &stacks, &goal, 100_000) {
+                    Some(moves) => print!("CrateMover {name}:\n{}", render(&stacks, &moves)),
+                    None => println!("CrateMover {name}: no plan found"),
+                }
+            }
+        }
+        if let Some(k) = arg.strip_prefix("--capacity=") {
+            let mut stacks = stacks.clone();
+            let lifts = simulate(&Capacity(k.parse()?), &instructions, &mut stacks)?;
+            println!("With capacity {k}: {} after {lifts} lifts", tops(&stacks));
+        }
+    }
     Ok(())
 }
 
@@ -98,20 +514,191 @@ mod tests {
 
     #[test]
     fn test_parse() {
-        let input = parse(&fs::read_to_string("inputs/day05_test").unwrap());
+        let input = parse(&fs::read_to_string("inputs/day05_test").unwrap()).unwrap();
         assert_eq!(input.0[0][0], 'Z');
         assert_eq!(input.1[0], (1, 2, 1));
     }
 
     #[test]
     fn test_part_one() {
-        let (mut stacks, instructions) = parse(&fs::read_to_string("inputs/day05_test").unwrap());
-        assert_eq!(part_one(&instructions, &mut stacks), "CMZ");
+        let (mut stacks, instructions) =
+            parse(&fs::read_to_string("inputs/day05_test").unwrap()).unwrap();
+        assert_eq!(part_one(&instructions, &mut stacks).unwrap(), "CMZ");
     }
 
     #[test]
     fn test_part_two() {
-        let (mut stacks, instructions) = parse(&fs::read_to_string("inputs/day05_test").unwrap());
-        assert_eq!(part_two(&instructions, &mut stacks), "MCD");
+        let (mut stacks, instructions) =
+            parse(&fs::read_to_string("inputs/day05_test").unwrap()).unwrap();
+        assert_eq!(part_two(&instructions, &mut stacks).unwrap(), "MCD");
+    }
+
+    #[test]
+    fn test_cranes() {
+        let (stacks, instructions) =
+            parse(&fs::read_to_string("inputs/day05_test").unwrap()).unwrap();
+        let run = |crane: &dyn Crane| {
+            let mut stacks = stacks.clone();
+            let lifts = simulate(crane, &instructions, &mut stacks).unwrap();
+            (tops(&stacks), lifts)
+        };
+
+        assert_eq!(run(&CrateMover9000), ("CMZ".to_string(), 7));
+        assert_eq!(run(&CrateMover9001), ("MCD".to_string(), 4));
+        assert_eq!(run(&Capacity(1)), ("CMZ".to_string(), 7));
+        assert_eq!(run(&Capacity(2)), ("MCZ".to_string(), 5));
+        assert_eq!(Capacity(3).lifts(7), vec![3, 3, 1]);
+    }
+
+    #[test]
+    fn test_move_errors() {
+        let (mut stacks, _) = parse(&fs::read_to_string("inputs/day05_test").unwrap()).unwrap();
+
+        let error = simulate(&CrateMover9000, &[(1, 2, 1), (1, 4, 1)], &mut stacks).unwrap_err();
+        assert_eq!(error, MoveError::NoSuchStack { step: 2, stack: 4 });
+        assert_eq!(tops(&stacks), "DCP");
+
+        let error = simulate(&CrateMover9001, &[(5, 1, 3)], &mut stacks).unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "move 1: can't take 5 crates from stack 1 holding 3"
+        );
+        assert_eq!(
+            simulate(&CrateMover9000, &[(1, 0, 1)], &mut stacks),
+            Err(MoveError::NoSuchStack { step: 1, stack: 0 })
+        );
+    }
+
+    #[test]
+    fn test_draw() {
+        let input = fs::read_to_string("inputs/day05_test").unwrap();
+        let (stacks, instructions) = parse(&input).unwrap();
+        assert_eq!(render(&stacks, &instructions), input);
+
+        let mut many: Vec<Vec<char>> = (0..12).map(|i| vec!['A'; i % 3]).collect();
+        many[10] = vec!['X', 'Y', 'Z'];
+        let drawing = draw(&many);
+        assert!(drawing.ends_with(" 9  10  11  12 \n"));
+        assert_eq!(parse(&drawing).unwrap().0, many);
+
+        // without trailing spaces
+        let trimmed: Vec<&str> = drawing.lines().map(str::trim_end).collect();
+        assert_eq!(parse(&trimmed.join("\n")).unwrap().0, many);
+    }
+
+    #[test]
+    fn test_parse_errors() {
+        let error = |s| parse(s).unwrap_err().to_string();
+
+        assert_eq!(error(""), "line 1, column 1: no stack labels");
+        assert_eq!(
+            error("[A]\n 1   3"),
+            "line 2, column 6: stack labels must count up from 1"
+        );
+        assert_eq!(
+            error("[A] (B)\n 1   2"),
+            "line 1, column 5: expected a crate like '[A]'"
+        );
+        assert_eq!(
+            error("[A]     [B]\n 1   2"),
+            "line 1, column 9: crate not above a stack label"
+        );
+        assert_eq!(
+            error("    [B]\n[A]\n 1   2"),
+            "line 1, column 5: crate without a crate below"
+        );
+        assert_eq!(
+            error("[A]\n 1\n\nmove 1 from 1"),
+            "line 4, column 1: expected 'move N from A to B'"
+        );
+    }
+
+    #[test]
+    fn test_plan() {
+        let (stacks, instructions) =
+            parse(&fs::read_to_string("inputs/day05_test").unwrap()).unwrap();
+
+        let moves = plan(
+            &CrateMover9000,
+            &stacks,
+            &Goal::Tops("CMZ".to_string()),
+            10_000,
+        )
+        .unwrap();
+        assert!(moves.len() <= instructions.len());
+        // the plan round-trips through the puzzle syntax
+        let (mut planned, moves) = parse(&render(&stacks, &moves)).unwrap();
+        assert_eq!(part_one(&moves, &mut planned).unwrap(), "CMZ");
+
+        let mut target = stacks.clone();
+        simulate(&CrateMover9001, &instructions, &mut target).unwrap();
+        let goal = Goal::Stacks(target.clone());
+        let moves = plan(&CrateMover9001, &stacks, &goal, 10_000).unwrap();
+        assert!(moves.len() <= instructions.len());
+        let mut planned = stacks.clone();
+        simulate(&CrateMover9001, &moves, &mut planned).unwrap();
+        assert_eq!(planned, target);
+
+        assert_eq!(
+            plan(&CrateMover9000, &stacks, &Goal::Tops("NDP".to_string()), 1),
+            Some(vec![])
+        );
+        assert_eq!(
+            plan(
+                &CrateMover9000,
+                &stacks,
+                &Goal::Tops("XYZ".to_string()),
+                10_000
+            ),
+            None
+        );
+        assert_eq!(
+            plan(
+                &CrateMover9000,
+                &stacks,
+                &Goal::Tops("ZZ".to_string()),
+                10_000
+            ),
+            None
+        );
+
+        // too few arrangements to search, so the moves are built
+        let moves = plan(&CrateMover9001, &stacks, &Goal::Tops("CMZ".to_string()), 1).unwrap();
+        let mut planned = stacks.clone();
+        assert_eq!(part_two(&moves, &mut planned).unwrap(), "CMZ");
+    }
+
+    #[test]
+    fn test_plan_nine_stacks() {
+        let stacks: Vec<Vec<char>> = (0..9)
+            .map(|i| {
+                (0..3 + i * 5 % 6)
+                    .map(|j| char::from(b'A' + ((i * 7 + j * 3) % 26) as u8))
+                    .collect()
+            })
+            .collect();
+        let cranes: [&dyn Crane; 3] = [&CrateMover9000, &CrateMover9001, &Capacity(2)];
+
+        // every top moves one stack to the left
+        let mut rotated: Vec<char> = tops(&stacks).chars().collect();
+        rotated.rotate_left(1);
+        let goal = Goal::Tops(rotated.iter().collect());
+        for crane in cranes {
+            let moves = plan(crane, &stacks, &goal, 1_000).unwrap();
+            let mut planned = stacks.clone();
+            simulate(crane, &moves, &mut planned).unwrap();
+            assert!(goal.is_reached(&planned));
+        }
+
+        let mut target = stacks.clone();
+        simulate(
+            &CrateMover9001,
+            &[(3, 1, 9), (2, 4, 5), (1, 9, 2)],
+            &mut target,
+        )
+        .unwrap();
+        let goal = Goal::Stacks(target.clone());
+        let moves = plan(&CrateMover9001, &stacks, &goal, 10_000).unwrap();
+        assert!(moves.len() <= 3);
     }
 }
@@ -1,46 +1,219 @@
 use rustvent2022::get_input;
+use std::collections::{HashMap, VecDeque};
+use std::env;
+use std::fs::File;
+use std::hash::Hash;
+use std::io::{self, BufReader, Read};
 
-fn has_duplicate(s: &str) -> bool {
-    for i in 0..s.len() - 1 {
-        for j in i + 1..s.len() {
-            if s.chars().nth(i) == s.chars().nth(j) {
-                return true;
+/// The last `len` symbols of a stream, with how often each symbol occurs in
+/// them. Symbols are usually bytes, but may be `char`s or anything hashable.
+struct Window<T> {
+    len: usize,
+    symbols: VecDeque<T>,
+    counts: HashMap<T, usize>,
+    /// Number of distinct symbols occurring more than once.
+    duplicates: usize,
+    position: usize,
+}
+
+impl<T: Copy + Eq + Hash> Window<T> {
+    fn new(len: usize) -> Self {
+        Window {
+            len,
+            symbols: VecDeque::with_capacity(len + 1),
+            counts: HashMap::new(),
+            duplicates: 0,
+            position: 0,
+        }
+    }
+
+    /// Adds the next symbol and returns the position after it if the window
+    /// then holds a marker, i.e. `len` distinct symbols.
+    fn push(&mut self, symbol: T) -> Option<usize> {
+        self.position += 1;
+        self.symbols.push_back(symbol);
+        let count = self.counts.entry(symbol).or_insert(0);
+        *count += 1;
+        if *count == 2 {
+            self.duplicates += 1;
+        }
+
+        if self.symbols.len() > self.len {
+            let old = self.symbols.pop_front()?;
+            let count = self.counts.get_mut(&old)?;
+            if *count == 2 {
+                self.duplicates -= 1;
+            }
+            *count -= 1;
+            if *count == 0 {
+                self.counts.remove(&old);
+            }
+        }
+
+        (self.symbols.len() == self.len && self.duplicates == 0).then_some(self.position)
+    }
+
+    fn is_full(&self) -> bool {
+        self.symbols.len() == self.len
+    }
+
+    /// The symbols occurring most often in the window, in the order of their
+    /// first occurrence, and how often they occur.
+    fn most_frequent(&self) -> (Vec<T>, usize) {
+        let max = self.counts.values().copied().max().unwrap_or(0);
+        let mut most = Vec::new();
+        for symbol in &self.symbols {
+            if self.counts[symbol] == max && !most.contains(symbol) {
+                most.push(*symbol);
             }
         }
+        (most, max)
+    }
+}
+
+/// Iterator over the positions of all markers, see `markers`.
+struct Markers<I: Iterator> {
+    symbols: I,
+    window: Window<I::Item>,
+}
+
+impl<I> Iterator for Markers<I>
+where
+    I: Iterator,
+    I::Item: Copy + Eq + Hash,
+{
+    type Item = usize;
+
+    fn next(&mut self) -> Option<usize> {
+        self.symbols.find_map(|symbol| self.window.push(symbol))
+    }
+}
+
+/// All positions after which the last `len` symbols are distinct, looking at
+/// every symbol once.
+fn markers<I>(symbols: I, len: usize) -> Markers<I::IntoIter>
+where
+    I: IntoIterator,
+    I::Item: Copy + Eq + Hash,
+{
+    Markers {
+        symbols: symbols.into_iter(),
+        window: Window::new(len),
+    }
+}
+
+fn read_markers<R: Read>(reader: R, len: usize) -> io::Result<Vec<usize>> {
+    let mut window = Window::new(len);
+    let mut found = Vec::new();
+    for byte in BufReader::new(reader).bytes() {
+        found.extend(window.push(byte?));
+    }
+    Ok(found)
+}
+
+/// Splits a stream into the messages following each marker of `len`
+/// distinct symbols. A message ends where the next marker starts, so it
+/// can't contain `len` distinct symbols in a row itself, and anything before
+/// the first marker is dropped.
+fn messages<T: Copy + Eq + Hash>(stream: &[T], len: usize) -> Vec<&[T]> {
+    let mut found = Vec::new();
+    let Some(mut start) = markers(stream.iter().copied(), len).next() else {
+        return found;
+    };
+    while let Some(end) = markers(stream[start..].iter().copied(), len).next() {
+        found.push(&stream[start..start + end - len]);
+        start += end;
     }
-    false
+    found.push(&stream[start..]);
+    found
 }
 
-fn part(input: &str, len: usize) -> usize {
-    for i in len..input.len() {
-        if !has_duplicate(&input[i - len..i]) {
-            return i;
+/// Start and length of the first longest run of distinct symbols.
+fn longest_distinct_run<T: Copy + Eq + Hash>(stream: &[T]) -> (usize, usize) {
+    let mut last_seen = HashMap::new();
+    let (mut start, mut best) = (0, (0, 0));
+    for (i, symbol) in stream.iter().enumerate() {
+        if let Some(seen) = last_seen.insert(*symbol, i) {
+            start = start.max(seen + 1);
+        }
+        if i + 1 - start > best.1 {
+            best = (start, i + 1 - start);
         }
     }
-    panic!("Input is not correct, should not be here.");
+    best
+}
+
+#[derive(Debug, PartialEq)]
+struct WindowStats<T> {
+    /// Position after the window.
+    end: usize,
+    distinct: usize,
+    most_frequent: Vec<T>,
+    count: usize,
 }
 
-// fn part_two(instructions: &Vec<(usize, usize, usize)>, stacks: &mut Vec<Vec<char>>) -> String {
-//     for (n, from, to) in instructions {
-//         let len = stacks[*from - 1].len();
-//         let mut tail = stacks[*from - 1].split_off(len.saturating_sub(*n));
-//         stacks[*to - 1].append(&mut tail);
-//     }
+/// Statistics of every window of `len` symbols. Finding the most frequent
+/// symbols looks at the whole window, so this is linear in the stream only
+/// for a fixed window length.
+fn window_stats<T: Copy + Eq + Hash>(stream: &[T], len: usize) -> Vec<WindowStats<T>> {
+    let mut window = Window::new(len);
+    let mut stats = Vec::new();
+    for symbol in stream {
+        window.push(*symbol);
+        if window.is_full() {
+            let (most_frequent, count) = window.most_frequent();
+            stats.push(WindowStats {
+                end: window.position,
+                distinct: window.counts.len(),
+                most_frequent,
+                count,
+            });
+        }
+    }
+    stats
+}
 
-//     let mut s = String::with_capacity(stacks.len());
-//     for stack in stacks {
-//         match stack.last() {
-//             Some(c) => s.push(*c),
-//             None => (),
-//         }
-//     }
-//     s
-// }
+fn part(input: &str, len: usize) -> Option<usize> {
+    markers(input.bytes(), len).next()
+}
 
 fn main() -> Result<(), Box<dyn std::error::Error + 'static>> {
     let input = get_input("2022", "6");
-    println!("Solution part one: {}", part(&input, 4));
-    println!("Solution part two: {}", part(&input, 14));
+    let input = input.trim_end();
+    println!(
+        "Solution part one: {}",
+        part(input, 4).ok_or("no start-of-packet marker")?
+    );
+    println!(
+        "Solution part two: {}",
+        part(input, 14).ok_or("no start-of-message marker")?
+    );
+
+    let symbols: Vec<char> = input.chars().collect();
+    for arg in env::args().skip(1) {
+        if let Some(len) = arg.strip_prefix("--messages=") {
+            for message in messages(&symbols, len.parse()?) {
+                println!("{}", message.iter().collect::<String>());
+            }
+        }
+        if let Some(len) = arg.strip_prefix("--stats=") {
+            let (start, run) = longest_distinct_run(&symbols);
+            println!("Longest distinct run: {run} symbols from {start}");
+            for stats in window_stats(&symbols, len.parse()?) {
+                let most: String = stats.most_frequent.iter().collect();
+                println!(
+                    "{}: {} distinct, '{most}' {} times",
+                    stats.end, stats.distinct, stats.count
+                );
+            }
+        }
+        if let Some(path) = arg.strip_prefix("--read=") {
+            for len in [4, 14] {
+                let found = read_markers(File::open(path)?, len)?;
+                println!("Markers of length {len}: {found:?}");
+            }
+        }
+    }
     Ok(())
 }
 
@@ -51,7 +224,63 @@ mod tests {
     #[test]
     fn test() {
         let test = "bvwbjplbgvbhsrlpgdmjqwftvncz";
-        assert_eq!(part(&test, 4), 5);
-        assert_eq!(part(&test, 14), 23);
+        assert_eq!(part(test, 4), Some(5));
+        assert_eq!(part(test, 14), Some(23));
+    }
+
+    #[test]
+    fn test_markers() {
+        assert_eq!(
+            markers("abcabbcda".bytes(), 3).collect::<Vec<_>>(),
+            vec![3, 4, 5, 8, 9]
+        );
+        assert_eq!(part("aabbaabb", 3), None);
+        assert_eq!(part("", 4), None);
+        assert_eq!(
+            read_markers("nppdvjthqldpwncqszvftbrmjlhg".as_bytes(), 4).unwrap()[0],
+            6
+        );
+    }
+
+    #[test]
+    fn test_messages() {
+        let stream: Vec<char> = "xxxaabcdhhhiiihhwxyzokk".chars().collect();
+        let found: Vec<String> = messages(&stream, 4)
+            .into_iter()
+            .map(|m| m.iter().collect())
+            .collect();
+        assert_eq!(found, vec!["hhhiiih", "zokk"]);
+        assert!(messages(b"aaaa", 2).is_empty());
+    }
+
+    #[test]
+    fn test_non_ascii() {
+        let stream: Vec<char> = "ααβγαδε🎄".chars().collect();
+        assert_eq!(
+            markers(stream.iter().copied(), 4).collect::<Vec<_>>(),
+            vec![6, 7, 8]
+        );
+        assert_eq!(longest_distinct_run(&stream), (2, 6));
+        // as bytes, the marker needs the last byte of 'ε' and the first of '🎄'
+        assert_eq!(part("ααβγαδε🎄", 4), Some(15));
+    }
+
+    #[test]
+    fn test_window_stats() {
+        let stats = window_stats(b"abbcab", 4);
+        assert_eq!(stats.len(), 3);
+        assert_eq!(
+            stats[0],
+            WindowStats {
+                end: 4,
+                distinct: 3,
+                most_frequent: vec![b'b'],
+                count: 2
+            }
+        );
+        let tie = &window_stats(b"abbcab", 3)[2];
+        assert_eq!((tie.most_frequent.clone(), tie.count), (b"bca".to_vec(), 1));
+        assert_eq!(longest_distinct_run(b"abcabcbb"), (0, 3));
+        assert_eq!(longest_distinct_run(b""), (0, 0));
     }
 }
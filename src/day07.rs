@@ -1,10 +1,24 @@
 use rustvent2022::get_input;
-use std::cell::RefCell;
-use std::rc::Rc;
+use std::cmp::Reverse;
+use std::collections::VecDeque;
+use std::env;
+use std::error::Error;
+use std::fmt;
+use std::io;
+use std::ops::{Index, RangeInclusive};
+use std::path::Path;
+use std::str::FromStr;
+
+/// Index of a directory in a `FileSystem`.
+type Id = usize;
+
+const ROOT: Id = 0;
+const DISK_SIZE: usize = 70_000_000;
+const NEEDED_SIZE: usize = 30_000_000;
 
 #[derive(Debug)]
 enum DirectoryEntry {
-    Directory(Rc<RefCell<Directory>>),
+    Directory(Id),
     File(File),
 }
 
@@ -12,7 +26,9 @@ enum DirectoryEntry {
 struct Directory {
     pub entries: Vec<DirectoryEntry>,
     pub name: String,
-    pub parent: Option<Rc<RefCell<Directory>>>,
+    pub parent: Option<Id>,
+    /// Total size of all files below, kept up to date by `FileSystem`.
+    pub size: usize,
 }
 
 #[derive(Debug)]
@@ -21,134 +37,694 @@ struct File {
     pub name: String,
 }
 
-impl Directory {
-    fn cd(&self, name: &str) -> Option<Rc<RefCell<Directory>>> {
-        for entry in &self.entries {
-            if let DirectoryEntry::Directory(dir) = entry {
-                if dir.borrow().name == name {
-                    return Some(Rc::clone(&dir));
-                }
+/// All directories live in one arena and refer to each other by index, the
+/// root being `ROOT`.
+#[derive(Debug)]
+struct FileSystem {
+    dirs: Vec<Directory>,
+}
+
+impl Index<Id> for FileSystem {
+    type Output = Directory;
+
+    fn index(&self, id: Id) -> &Directory {
+        &self.dirs[id]
+    }
+}
+
+impl FileSystem {
+    fn new() -> Self {
+        FileSystem {
+            dirs: vec![Directory {
+                entries: Vec::new(),
+                name: String::new(),
+                parent: None,
+                size: 0,
+            }],
+        }
+    }
+
+    fn cd(&self, dir: Id, name: &str) -> Option<Id> {
+        self[dir].entries.iter().find_map(|entry| match entry {
+            DirectoryEntry::Directory(d) if self[*d].name == name => Some(*d),
+            _ => None,
+        })
+    }
+
+    fn has_file(&self, dir: Id, name: &str) -> bool {
+        self[dir]
+            .entries
+            .iter()
+            .any(|entry| matches!(entry, DirectoryEntry::File(f) if f.name == name))
+    }
+
+    /// Creates a directory, or returns the existing one of that name.
+    fn mkdir(&mut self, parent: Id, name: &str) -> Result<Id, ErrorKind> {
+        if let Some(id) = self.cd(parent, name) {
+            return Ok(id);
+        }
+        if self.has_file(parent, name) {
+            return Err(ErrorKind::NameClash(name.to_string()));
+        }
+        let id = self.dirs.len();
+        self.dirs.push(Directory {
+            entries: Vec::new(),
+            name: name.to_string(),
+            parent: Some(parent),
+            size: 0,
+        });
+        self.dirs[parent]
+            .entries
+            .push(DirectoryEntry::Directory(id));
+        Ok(id)
+    }
+
+    /// Adds a file, or updates the size of the existing one of that name, and
+    /// updates the sizes of `dir` and all directories above.
+    fn add_file(&mut self, dir: Id, name: &str, size: usize) -> Result<(), ErrorKind> {
+        if self.cd(dir, name).is_some() {
+            return Err(ErrorKind::NameClash(name.to_string()));
+        }
+        let existing = self.dirs[dir]
+            .entries
+            .iter_mut()
+            .find_map(|entry| match entry {
+                DirectoryEntry::File(f) if f.name == name => Some(f),
+                _ => None,
+            });
+        let old = match existing {
+            Some(f) => std::mem::replace(&mut f.size, size),
+            None => {
+                self.dirs[dir].entries.push(DirectoryEntry::File(File {
+                    size,
+                    name: name.to_string(),
+                }));
+                0
             }
+        };
+
+        let mut current = Some(dir);
+        while let Some(id) = current {
+            self.dirs[id].size = self.dirs[id].size - old + size;
+            current = self.dirs[id].parent;
         }
-        None
+        Ok(())
     }
 
-    fn size(&self) -> usize {
-        let mut size = 0;
+    /// Follows a path from `dir` like `cd` would: absolute paths start at the
+    /// root and `..` at the root stays there.
+    fn resolve(&self, dir: Id, path: &str) -> Option<Id> {
+        let start = if path.starts_with('/') { ROOT } else { dir };
+        path.split('/').try_fold(start, |dir, name| match name {
+            "" | "." => Some(dir),
+            ".." => Some(self[dir].parent.unwrap_or(ROOT)),
+            _ => self.cd(dir, name),
+        })
+    }
 
-        for entry in &self.entries {
-            size += match entry {
-                DirectoryEntry::File(f) => f.size,
-                DirectoryEntry::Directory(d) => d.borrow().size(),
+    /// Finds a directory by an absolute path like `/a/e`.
+    fn lookup(&self, path: &str) -> Option<Id> {
+        if !path.starts_with('/') {
+            return None;
+        }
+        self.resolve(ROOT, path)
+    }
+
+    fn path(&self, dir: Id) -> String {
+        let mut names = Vec::new();
+        let mut current = dir;
+        while let Some(parent) = self[current].parent {
+            names.push(self[current].name.as_str());
+            current = parent;
+        }
+        names.reverse();
+        format!("/{}", names.join("/"))
+    }
+
+    fn depth(&self, dir: Id) -> usize {
+        std::iter::successors(self[dir].parent, |&d| self[d].parent).count()
+    }
+
+    /// Renders the tree like the puzzle does, entries in the order they were
+    /// listed or, if `sorted`, by name.
+    fn tree(&self, sorted: bool) -> String {
+        let mut out = String::new();
+        self.render_tree(ROOT, 0, sorted, &mut out);
+        out
+    }
+
+    fn render_tree(&self, dir: Id, indent: usize, sorted: bool, out: &mut String) {
+        let name = if dir == ROOT { "/" } else { &self[dir].name };
+        out.push_str(&format!("{:indent$}- {name} (dir)\n", ""));
+
+        let mut entries: Vec<_> = self[dir].entries.iter().collect();
+        if sorted {
+            entries.sort_by_key(|entry| match entry {
+                DirectoryEntry::Directory(d) => &self[*d].name,
+                DirectoryEntry::File(f) => &f.name,
+            });
+        }
+        for entry in entries {
+            match entry {
+                DirectoryEntry::Directory(d) => self.render_tree(*d, indent + 2, sorted, out),
+                DirectoryEntry::File(f) => out.push_str(&format!(
+                    "{:width$}- {} (file, size={})\n",
+                    "",
+                    f.name,
+                    f.size,
+                    width = indent + 2
+                )),
             }
         }
+    }
 
-        size
+    /// Directories no deeper than `max_depth` below the root, largest first
+    /// and ties by path.
+    fn du(&self, max_depth: Option<usize>) -> Vec<Id> {
+        let mut dirs: Vec<Id> = self
+            .dirs()
+            .map(|(id, _)| id)
+            .filter(|&id| max_depth.is_none_or(|max| self.depth(id) <= max))
+            .collect();
+        dirs.sort_by_cached_key(|&id| (Reverse(self[id].size), self.path(id)));
+        dirs
     }
 
-    fn part_two(&self) -> usize {
-        let needed_size = 30000000 - (70000000 - self.size());
-        let sizes = self._part_two(&needed_size);
-        *sizes.iter().min().unwrap()
+    /// All directories, starting with the root.
+    fn dirs(&self) -> impl Iterator<Item = (Id, &Directory)> {
+        self.dirs.iter().enumerate()
     }
 
-    fn _part_two(&self, needed: &usize) -> Vec<usize> {
-        let mut sizes = Vec::<usize>::new();
-        let own_size = self.size();
-        if own_size >= *needed {
-            sizes.push(own_size);
+    fn file_path(&self, dir: Id, file: &File) -> String {
+        match dir {
+            ROOT => format!("/{}", file.name),
+            _ => format!("{}/{}", self.path(dir), file.name),
         }
+    }
 
-        for entry in &self.entries {
-            if let DirectoryEntry::Directory(d) = entry {
-                sizes.append(&mut d.borrow()._part_two(needed))
+    /// Directories matching the query, in the order they were found.
+    fn find_dirs(&self, query: &Query) -> Vec<Id> {
+        self.dirs()
+            .filter(|&(id, d)| query.matches(&d.name, d.size, || self.path(id), || self.depth(id)))
+            .map(|(id, _)| id)
+            .collect()
+    }
+
+    /// Files matching the query, with the directory they are in.
+    fn find_files(&self, query: &Query) -> Vec<(Id, &File)> {
+        self.dirs()
+            .flat_map(|(id, d)| {
+                d.entries.iter().filter_map(move |entry| match entry {
+                    DirectoryEntry::File(f) => Some((id, f)),
+                    DirectoryEntry::Directory(_) => None,
+                })
+            })
+            .filter(|&(id, f)| {
+                query.matches(
+                    &f.name,
+                    f.size,
+                    || self.file_path(id, f),
+                    || self.depth(id) + 1,
+                )
+            })
+            .collect()
+    }
+
+    /// How much space has to be freed so that `required` is free on a disk of
+    /// size `disk`.
+    fn needed(&self, disk: usize, required: usize) -> usize {
+        (required + self[ROOT].size).saturating_sub(disk)
+    }
+
+    /// The smallest directory freeing at least `needed` when deleted. Of
+    /// several as small, this is the one created last, so never an ancestor
+    /// of another.
+    fn smallest_dir_freeing(&self, needed: usize) -> Option<Id> {
+        self.dirs()
+            .filter(|(_, d)| d.size >= needed)
+            .min_by_key(|&(id, d)| (d.size, Reverse(id)))
+            .map(|(id, _)| id)
+    }
+
+    /// The directories freeing at least `needed` with the least space in
+    /// total, or `None` if even deleting everything is not enough. This
+    /// searches branch and bound, starting from `smallest_dir_freeing` and
+    /// deciding for one directory after the other whether to delete it or
+    /// look inside, skipping whatever can't beat the best set so far. After
+    /// `MAX_STEPS` decisions it settles for the best set found, which then
+    /// isn't known to be optimal.
+    fn smallest_set_freeing(&self, needed: usize) -> Option<Deletion> {
+        let single = self.smallest_dir_freeing(needed)?;
+        let mut search = Search {
+            fs: self,
+            needed,
+            chosen: Vec::new(),
+            best: (self[single].size, vec![single]),
+            steps: 0,
+        };
+        search.run(&mut vec![ROOT], self[ROOT].size, 0);
+
+        let (_, mut dirs) = search.best;
+        dirs.sort_unstable();
+        Some(Deletion {
+            dirs,
+            optimal: search.steps <= MAX_STEPS,
+        })
+    }
+
+    /// Reads a real directory, entries sorted by name. Symlinks and other
+    /// special files are skipped.
+    fn from_path(path: &Path) -> io::Result<Self> {
+        let mut fs = FileSystem::new();
+        fs.read_dir(ROOT, path)?;
+        Ok(fs)
+    }
+
+    fn read_dir(&mut self, dir: Id, path: &Path) -> io::Result<()> {
+        let mut entries = std::fs::read_dir(path)?.collect::<Result<Vec<_>, _>>()?;
+        entries.sort_by_key(|entry| entry.file_name());
+
+        for entry in entries {
+            let name = entry.file_name().to_string_lossy().into_owned();
+            let clash = |_| io::Error::new(io::ErrorKind::AlreadyExists, name.clone());
+            let kind = entry.file_type()?;
+            if kind.is_dir() {
+                let id = self.mkdir(dir, &name).map_err(clash)?;
+                self.read_dir(id, &entry.path())?;
+            } else if kind.is_file() {
+                let size = entry.metadata()?.len() as usize;
+                self.add_file(dir, &name, size).map_err(clash)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Writes a terminal log which replays to this filesystem.
+    fn to_log(&self, options: &LogOptions) -> String {
+        let mut order = Vec::new();
+        let mut pending = VecDeque::from([ROOT]);
+        while let Some(dir) = match options.order {
+            Order::DepthFirst => pending.pop_back(),
+            Order::BreadthFirst => pending.pop_front(),
+        } {
+            order.push(dir);
+            let children = self[dir].entries.iter().filter_map(|entry| match entry {
+                DirectoryEntry::Directory(d) => Some(*d),
+                DirectoryEntry::File(_) => None,
+            });
+            match options.order {
+                Order::DepthFirst => pending.extend(children.rev()),
+                Order::BreadthFirst => pending.extend(children),
+            }
+        }
+
+        let mut log = String::from("$ cd /\n");
+        let mut current = ROOT;
+        for dir in order {
+            if options.absolute {
+                if dir != current {
+                    log.push_str(&format!("$ cd {}\n", self.path(dir)));
+                }
+            } else {
+                for step in self.route(current, dir) {
+                    log.push_str(&format!("$ cd {step}\n"));
+                }
+            }
+            current = dir;
+
+            for _ in 0..if options.redundant { 2 } else { 1 } {
+                log.push_str("$ ls\n");
+                for entry in &self[dir].entries {
+                    match entry {
+                        DirectoryEntry::Directory(d) => {
+                            log.push_str(&format!("dir {}\n", self[*d].name))
+                        }
+                        DirectoryEntry::File(f) => {
+                            log.push_str(&format!("{} {}\n", f.size, f.name))
+                        }
+                    }
+                }
+            }
+            if options.redundant {
+                log.push_str("$ cd /\n");
+                current = ROOT;
             }
         }
+        log
+    }
 
-        sizes
+    /// The `cd` arguments leading from one directory to another, one step
+    /// at a time.
+    fn route(&self, from: Id, to: Id) -> Vec<&str> {
+        let ancestors = |dir| {
+            let mut chain: Vec<Id> =
+                std::iter::successors(Some(dir), |&d| self[d].parent).collect();
+            chain.reverse();
+            chain
+        };
+        let (from, to) = (ancestors(from), ancestors(to));
+        let common = from.iter().zip(&to).take_while(|(a, b)| a == b).count();
+
+        std::iter::repeat_n("..", from.len() - common)
+            .chain(to[common..].iter().map(|&d| self[d].name.as_str()))
+            .collect()
     }
 
     fn part_one(&self) -> usize {
-        let mut size = 0;
+        self.dirs()
+            .map(|(_, d)| d.size)
+            .filter(|&size| size <= 100_000)
+            .sum()
+    }
 
-        for entry in &self.entries {
-            if let DirectoryEntry::Directory(d) = entry {
-                size += d.borrow().part_one();
+    fn part_two(&self) -> usize {
+        self.smallest_dir_freeing(self.needed(DISK_SIZE, NEEDED_SIZE))
+            .map_or(0, |id| self[id].size)
+    }
+}
+
+/// Most decisions `FileSystem::smallest_set_freeing` makes.
+const MAX_STEPS: usize = 1_000_000;
+
+#[derive(Debug, PartialEq)]
+struct Deletion {
+    dirs: Vec<Id>,
+    /// Whether no smaller set frees enough.
+    optimal: bool,
+}
+
+/// State of the search in `FileSystem::smallest_set_freeing`.
+struct Search<'a> {
+    fs: &'a FileSystem,
+    needed: usize,
+    chosen: Vec<Id>,
+    /// Space freed by the best set so far, and the set.
+    best: (usize, Vec<Id>),
+    steps: usize,
+}
+
+impl Search<'_> {
+    /// Decides on the `pending` directories, which together hold `available`,
+    /// after choosing directories freeing `freed`. Leaves `pending` as it
+    /// was.
+    fn run(&mut self, pending: &mut Vec<Id>, available: usize, freed: usize) {
+        if freed >= self.needed {
+            if freed < self.best.0 {
+                self.best = (freed, self.chosen.clone());
             }
+            return;
+        }
+        if freed + available < self.needed || self.best.0 == self.needed {
+            return;
+        }
+        self.steps += 1;
+        if self.steps > MAX_STEPS {
+            return;
         }
+        let Some(dir) = pending.pop() else {
+            return;
+        };
+        let size = self.fs[dir].size;
 
-        let own_size = self.size();
-        if own_size <= 100_000 {
-            size += own_size;
+        if freed + size < self.best.0 {
+            self.chosen.push(dir);
+            self.run(pending, available - size, freed + size);
+            self.chosen.pop();
         }
 
-        size
+        // largest directories last, to be decided on first
+        let len = pending.len();
+        let mut children: Vec<Id> = self.fs[dir]
+            .entries
+            .iter()
+            .filter_map(|entry| match entry {
+                DirectoryEntry::Directory(d) => Some(*d),
+                DirectoryEntry::File(_) => None,
+            })
+            .collect();
+        children.sort_by_key(|&d| self.fs[d].size);
+        let inside: usize = children.iter().map(|&d| self.fs[d].size).sum();
+        pending.extend(children);
+        self.run(pending, available - size + inside, freed);
+        pending.truncate(len);
+        pending.push(dir);
     }
+}
 
-    fn from_str(s: &str) -> Result<Rc<RefCell<Self>>, &str> {
-        // parent
-        let parent = Rc::new(RefCell::new(Directory {
-            entries: Vec::new(),
-            name: String::new(),
-            parent: None,
-        }));
-
-        let mut current = Rc::clone(&parent);
-
-        for line in s.lines() {
-            if line.starts_with("$ cd ") {
-                current = match &line[5..] {
-                    "/" => Rc::clone(&parent),
-                    ".." => Rc::clone(
-                        (current.borrow().parent)
-                            .as_ref()
-                            .ok_or("Dir has no Parent")?,
-                    ),
-                    d => current
-                        .borrow()
-                        .cd(d)
-                        .ok_or("cd-ing into a non-existing dir {d}")?,
-                }
-            } else if line.starts_with("$ ls") {
+#[derive(Debug, Default, Clone, Copy)]
+enum Order {
+    #[default]
+    DepthFirst,
+    BreadthFirst,
+}
+
+/// How `FileSystem::to_log` visits directories. With `absolute` it changes
+/// directory by absolute path instead of one step at a time, and with
+/// `redundant` it lists every directory twice and returns to the root after.
+#[derive(Debug, Default, Clone, Copy)]
+struct LogOptions {
+    order: Order,
+    absolute: bool,
+    redundant: bool,
+}
+
+/// Filters for directories and files, all of which have to match. Globs
+/// match the whole path, where `*` and `?` don't match `/` but `**` does.
+#[derive(Debug, Default)]
+struct Query<'a> {
+    size: Option<RangeInclusive<usize>>,
+    depth: Option<RangeInclusive<usize>>,
+    glob: Option<&'a str>,
+    extension: Option<&'a str>,
+}
+
+impl<'a> Query<'a> {
+    fn new() -> Self {
+        Self::default()
+    }
+
+    fn size(mut self, size: RangeInclusive<usize>) -> Self {
+        self.size = Some(size);
+        self
+    }
+
+    /// The root has depth 0, its entries 1.
+    fn depth(mut self, depth: RangeInclusive<usize>) -> Self {
+        self.depth = Some(depth);
+        self
+    }
+
+    fn glob(mut self, glob: &'a str) -> Self {
+        self.glob = Some(glob);
+        self
+    }
+
+    fn extension(mut self, extension: &'a str) -> Self {
+        self.extension = Some(extension);
+        self
+    }
+
+    /// Path and depth are only computed if a filter needs them.
+    fn matches(
+        &self,
+        name: &str,
+        size: usize,
+        path: impl FnOnce() -> String,
+        depth: impl FnOnce() -> usize,
+    ) -> bool {
+        self.size.as_ref().is_none_or(|r| r.contains(&size))
+            && self.extension.is_none_or(|e| {
+                name.rsplit_once('.')
+                    .is_some_and(|(stem, ext)| !stem.is_empty() && ext == e)
+            })
+            && self.depth.as_ref().is_none_or(|r| r.contains(&depth()))
+            && self.glob.is_none_or(|g| {
+                let pattern: Vec<char> = g.chars().collect();
+                let text: Vec<char> = path().chars().collect();
+                glob_match(&pattern, &text)
+            })
+    }
+}
+
+fn glob_match(pattern: &[char], text: &[char]) -> bool {
+    match pattern {
+        [] => text.is_empty(),
+        ['*', '*', rest @ ..] => (0..=text.len()).any(|i| glob_match(rest, &text[i..])),
+        ['*', rest @ ..] => (0..=text.len())
+            .take_while(|&i| i == 0 || text[i - 1] != '/')
+            .any(|i| glob_match(rest, &text[i..])),
+        ['?', rest @ ..] => matches!(text, [c, t @ ..] if *c != '/' && glob_match(rest, t)),
+        [p, rest @ ..] => matches!(text, [c, t @ ..] if c == p && glob_match(rest, t)),
+    }
+}
+
+#[derive(Debug, PartialEq)]
+enum ErrorKind {
+    UnknownCommand(String),
+    NoSuchDirectory(String),
+    UnexpectedOutput,
+    BadSize(String),
+    NameClash(String),
+}
+
+#[derive(Debug, PartialEq)]
+struct ReplayError {
+    line: usize,
+    kind: ErrorKind,
+}
+
+impl fmt::Display for ReplayError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "line {}: ", self.line)?;
+        match &self.kind {
+            ErrorKind::UnknownCommand(c) => write!(f, "unknown command '{c}'"),
+            ErrorKind::NoSuchDirectory(d) => write!(f, "no such directory '{d}'"),
+            ErrorKind::UnexpectedOutput => write!(f, "output outside of ls"),
+            ErrorKind::BadSize(s) => write!(f, "can't parse file size '{s}'"),
+            ErrorKind::NameClash(n) => write!(f, "'{n}' is both a file and a directory"),
+        }
+    }
+}
+
+impl Error for ReplayError {}
+
+impl FromStr for FileSystem {
+    type Err = ReplayError;
+
+    /// Replays a terminal log of `cd` and `ls` commands. Listing a directory
+    /// again only adds what is new, so logs may revisit directories.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut fs = FileSystem::new();
+        let mut current = ROOT;
+        let mut listing = false;
+
+        for (n, line) in s.lines().enumerate() {
+            let error = |kind| ReplayError { line: n + 1, kind };
+            if line.trim().is_empty() {
                 continue;
-            } else {
-                if line.starts_with("dir") {
-                    current
-                        .borrow_mut()
-                        .entries
-                        .push(DirectoryEntry::Directory(Rc::new(RefCell::new(
-                            Directory {
-                                entries: Vec::new(),
-                                name: String::from(&line[4..]),
-                                parent: Some(Rc::clone(&current)),
-                            },
-                        ))));
-                } else {
-                    let parts: Vec<&str> = line.split(" ").collect();
-                    if parts.len() > 2 {
-                        return Err("Line should be a file and has not the right format.");
-                    }
+            }
 
-                    current
-                        .borrow_mut()
-                        .entries
-                        .push(DirectoryEntry::File(File {
-                            size: parts[0].parse().or(Err("Cant parse file len"))?,
-                            name: String::from(parts[1]),
-                        }));
+            if let Some(command) = line.strip_prefix("$ ") {
+                listing = false;
+                let command = command.trim();
+                if let Some(path) = command.strip_prefix("cd ") {
+                    current = fs
+                        .resolve(current, path)
+                        .ok_or_else(|| error(ErrorKind::NoSuchDirectory(path.to_string())))?;
+                } else if command == "ls" {
+                    listing = true;
+                } else {
+                    return Err(error(ErrorKind::UnknownCommand(command.to_string())));
                 }
+            } else if !listing {
+                return Err(error(ErrorKind::UnexpectedOutput));
+            } else if let Some(name) = line.strip_prefix("dir ") {
+                fs.mkdir(current, name).map_err(error)?;
+            } else {
+                let (size, name) = line.split_once(' ').unwrap_or((line, ""));
+                let size = size
+                    .parse()
+                    .map_err(|_| error(ErrorKind::BadSize(size.to_string())))?;
+                fs.add_file(current, name, size).map_err(error)?;
             }
         }
 
-        Ok(parent)
+        Ok(fs)
     }
 }
 
 fn main() -> Result<(), Box<dyn std::error::Error + 'static>> {
     let input = get_input("2022", "7");
-    let dir = Directory::from_str(&input)?;
-    println!("Solution part one: {}", dir.borrow().part_one());
-    println!("Solution part one: {}", dir.borrow().part_two());
+    let fs = FileSystem::from_str(&input)?;
+    println!("Solution part one: {}", fs.part_one());
+    println!("Solution part two: {}", fs.part_two());
+
+    let mut query = Query::new();
+    let mut find = false;
+    let mut options = LogOptions::default();
+    let mut generate = None;
+    let args: Vec<String> = env::args().skip(1).collect();
+    for arg in &args {
+        if let Some(glob) = arg.strip_prefix("--find=") {
+            query = query.glob(glob);
+            find = true;
+        }
+        if let Some(extension) = arg.strip_prefix("--ext=") {
+            query = query.extension(extension);
+            find = true;
+        }
+        if let Some(size) = arg.strip_prefix("--max-size=") {
+            query = query.size(0..=size.parse()?);
+            find = true;
+        }
+        if let Some(depth) = arg.strip_prefix("--max-depth=") {
+            query = query.depth(0..=depth.parse()?);
+            find = true;
+        }
+        if let Some((disk, required)) = arg.strip_prefix("--plan=").and_then(|p| p.split_once(','))
+        {
+            let needed = fs.needed(disk.parse()?, required.parse()?);
+            println!("Need to free {needed}");
+            if let Some(dir) = fs.smallest_dir_freeing(needed) {
+                println!("Delete {} ({})", fs.path(dir), fs[dir].size);
+            }
+            if let Some(deletion) = fs.smallest_set_freeing(needed) {
+                if !deletion.optimal {
+                    println!("Smallest set found, maybe not the smallest:");
+                }
+                for dir in deletion.dirs {
+                    println!("Or delete {} ({})", fs.path(dir), fs[dir].size);
+                }
+            }
+        }
+        match arg.as_str() {
+            "--bfs" => options.order = Order::BreadthFirst,
+            "--absolute" => options.absolute = true,
+            "--redundant" => options.redundant = true,
+            _ => (),
+        }
+        if let Some(path) = arg.strip_prefix("--generate=") {
+            generate = Some(path);
+        }
+        if arg == "--tree" || arg == "--tree-sorted" {
+            print!("{}", fs.tree(arg == "--tree-sorted"));
+        }
+        let depth = match arg.strip_prefix("--du") {
+            Some("") => Some(None),
+            Some(n) => n.strip_prefix('=').map(str::parse).transpose()?.map(Some),
+            None => None,
+        };
+        if let Some(depth) = depth {
+            for dir in fs.du(depth) {
+                println!("{}\t{}", fs[dir].size, fs.path(dir));
+            }
+        }
+        if let Some(path) = arg.strip_prefix("--ls=") {
+            let dir = fs.lookup(path).ok_or("no such directory")?;
+            println!("{} ({})", fs.path(dir), fs[dir].size);
+            for entry in &fs[dir].entries {
+                match entry {
+                    DirectoryEntry::Directory(d) => println!("dir {}", fs[*d].name),
+                    DirectoryEntry::File(f) => println!("{} {}", f.size, f.name),
+                }
+            }
+        }
+    }
+
+    if let Some(path) = generate {
+        print!(
+            "{}",
+            FileSystem::from_path(Path::new(path))?.to_log(&options)
+        );
+    }
+    if find {
+        for dir in fs.find_dirs(&query) {
+            println!("{}\t{}", fs[dir].size, fs.path(dir));
+        }
+        for (dir, file) in fs.find_files(&query) {
+            println!("{}\t{}", file.size, fs.file_path(dir, file));
+        }
+    }
     Ok(())
 }
 
@@ -182,24 +758,322 @@ $ ls
 
     #[test]
     fn parse() {
-        let dir = Directory::from_str(TEST_INPUT).unwrap();
+        let fs = FileSystem::from_str(TEST_INPUT).unwrap();
 
-        assert_eq!(dir.borrow().entries.len(), 4);
-        match dir.borrow().entries.first().unwrap() {
+        assert_eq!(fs[ROOT].entries.len(), 4);
+        match fs[ROOT].entries.first().unwrap() {
             DirectoryEntry::File(_) => panic!(),
-            DirectoryEntry::Directory(d) => assert_eq!("a", &d.borrow().name),
+            DirectoryEntry::Directory(d) => assert_eq!("a", &fs[*d].name),
         };
     }
 
     #[test]
     fn test_part_one() {
-        let dir = Directory::from_str(TEST_INPUT).unwrap();
-        assert_eq!(95437, dir.borrow().part_one());
+        let fs = FileSystem::from_str(TEST_INPUT).unwrap();
+        assert_eq!(95437, fs.part_one());
     }
 
     #[test]
     fn test_part_two() {
-        let dir = Directory::from_str(TEST_INPUT).unwrap();
-        assert_eq!(24933642, dir.borrow().part_two());
+        let fs = FileSystem::from_str(TEST_INPUT).unwrap();
+        assert_eq!(24933642, fs.part_two());
+    }
+
+    #[test]
+    fn test_lookup() {
+        let fs = FileSystem::from_str(TEST_INPUT).unwrap();
+
+        let e = fs.lookup("/a/e").unwrap();
+        assert_eq!(fs[e].size, 584);
+        assert_eq!(fs.path(e), "/a/e");
+        assert_eq!(fs.lookup("/"), Some(ROOT));
+        assert_eq!(fs.lookup("/a/x"), None);
+        assert_eq!(fs.lookup("a"), None);
+        assert_eq!(fs[fs.lookup("/d").unwrap()].size, 24933642);
+        assert_eq!(fs[ROOT].size, 48381165);
+        assert_eq!(fs.dirs().count(), 4);
+    }
+
+    #[test]
+    fn test_replay() {
+        let log = "$ cd /a
+$ ls
+dir b c
+12 my file.txt
+$ cd ../..
+$ cd a/b c
+$ ls
+100 x
+$ cd /
+$ cd a
+$ ls
+dir b c
+20 my file.txt
+$ cd ./b c/..";
+        let fs = FileSystem::from_str(&format!("$ ls\ndir a\n{log}")).unwrap();
+
+        let a = fs.lookup("/a").unwrap();
+        assert_eq!(fs[a].entries.len(), 2);
+        assert_eq!(fs[a].size, 120);
+        assert_eq!(fs[ROOT].size, 120);
+        assert_eq!(fs.resolve(a, "b c/../.."), Some(ROOT));
+        assert_eq!(fs.lookup("/a/b c").map(|d| fs[d].size), Some(100));
+
+        let error = |s| FileSystem::from_str(s).unwrap_err().to_string();
+        assert_eq!(error("$ ls\n1 a\n$ pwd"), "line 3: unknown command 'pwd'");
+        assert_eq!(error("$ cd /x"), "line 1: no such directory '/x'");
+        assert_eq!(error("$ cd /\n1 a"), "line 2: output outside of ls");
+        assert_eq!(error("$ ls\nx a"), "line 2: can't parse file size 'x'");
+        assert_eq!(
+            error("$ ls\n1 a\ndir a"),
+            "line 3: 'a' is both a file and a directory"
+        );
+    }
+
+    #[test]
+    fn test_tree() {
+        let fs = FileSystem::from_str(TEST_INPUT).unwrap();
+
+        assert_eq!(
+            fs.tree(false),
+            "- / (dir)
+  - a (dir)
+    - e (dir)
+      - i (file, size=584)
+    - f (file, size=29116)
+    - g (file, size=2557)
+    - h.lst (file, size=62596)
+  - b.txt (file, size=14848514)
+  - c.dat (file, size=8504156)
+  - d (dir)
+    - j (file, size=4060174)
+    - d.log (file, size=8033020)
+    - d.ext (file, size=5626152)
+    - k (file, size=7214296)
+"
+        );
+        let sorted = fs.tree(true);
+        assert!(sorted.ends_with(
+            "  - d (dir)
+    - d.ext (file, size=5626152)
+    - d.log (file, size=8033020)
+    - j (file, size=4060174)
+    - k (file, size=7214296)
+"
+        ));
+
+        let paths = |depth| -> Vec<_> { fs.du(depth).into_iter().map(|d| fs.path(d)).collect() };
+        assert_eq!(paths(None), vec!["/", "/d", "/a", "/a/e"]);
+        assert_eq!(paths(Some(1)), vec!["/", "/d", "/a"]);
+        assert_eq!(paths(Some(0)), vec!["/"]);
+    }
+
+    #[test]
+    fn test_query() {
+        let fs = FileSystem::from_str(TEST_INPUT).unwrap();
+        let files = |query| -> Vec<_> {
+            fs.find_files(&query)
+                .into_iter()
+                .map(|(d, f)| fs.file_path(d, f))
+                .collect()
+        };
+        let dirs = |query| -> Vec<_> {
+            fs.find_dirs(&query)
+                .into_iter()
+                .map(|d| fs.path(d))
+                .collect()
+        };
+
+        assert_eq!(files(Query::new().extension("log")), vec!["/d/d.log"]);
+        assert_eq!(
+            files(Query::new().glob("/a/*")),
+            vec!["/a/f", "/a/g", "/a/h.lst"]
+        );
+        assert_eq!(
+            files(Query::new().glob("/**/?")),
+            vec!["/a/f", "/a/g", "/d/j", "/d/k", "/a/e/i"]
+        );
+        assert_eq!(
+            files(Query::new().size(8_000_000..=9_000_000)),
+            vec!["/c.dat", "/d/d.log"]
+        );
+        assert_eq!(files(Query::new().depth(3..=3)), vec!["/a/e/i"]);
+        assert_eq!(
+            dirs(Query::new().depth(1..=1).size(0..=100_000)),
+            vec!["/a"]
+        );
+        assert_eq!(dirs(Query::new().glob("/?*")), vec!["/a", "/d"]);
+
+        // path and depth are only needed by their filters
+        let query = Query::new().size(0..=10).extension("txt");
+        assert!(query.matches("a.txt", 5, || unreachable!(), || unreachable!()));
+    }
+
+    #[test]
+    fn test_plan() {
+        let fs = FileSystem::from_str(TEST_INPUT).unwrap();
+        let d = fs.lookup("/d");
+        assert_eq!(fs.needed(DISK_SIZE, NEEDED_SIZE), 8381165);
+        assert_eq!(fs.smallest_dir_freeing(8381165), d);
+        let dirs = |needed| {
+            let deletion = fs.smallest_set_freeing(needed)?;
+            assert!(deletion.optimal);
+            Some(deletion.dirs)
+        };
+        assert_eq!(dirs(8381165), d.map(|d| vec![d]));
+        assert_eq!(dirs(0), Some(vec![]));
+        assert_eq!(dirs(50_000_000), None);
+
+        let fs = FileSystem::from_str(
+            "$ ls
+dir p
+$ cd p
+$ ls
+5000 big
+dir x
+dir y
+$ cd x
+$ ls
+600 a
+$ cd ../y
+$ ls
+500 b",
+        )
+        .unwrap();
+        let (x, y) = (fs.lookup("/p/x").unwrap(), fs.lookup("/p/y").unwrap());
+        assert_eq!(fs.smallest_dir_freeing(1000), fs.lookup("/p"));
+        let dirs = |needed| fs.smallest_set_freeing(needed).map(|d| d.dirs);
+        assert_eq!(dirs(1000), Some(vec![x, y]));
+        assert_eq!(dirs(550), Some(vec![x]));
+    }
+
+    fn assert_round_trip(fs: &FileSystem) {
+        for order in [Order::DepthFirst, Order::BreadthFirst] {
+            for (absolute, redundant) in
+                [(false, false), (true, false), (false, true), (true, true)]
+            {
+                let options = LogOptions {
+                    order,
+                    absolute,
+                    redundant,
+                };
+                let log = fs.to_log(&options);
+                let replayed = FileSystem::from_str(&log).unwrap();
+
+                assert_eq!(replayed.tree(false), fs.tree(false), "{options:?}");
+                let sizes = |fs: &FileSystem| -> Vec<_> {
+                    fs.du(None)
+                        .into_iter()
+                        .map(|d| (fs.path(d), fs[d].size))
+                        .collect()
+                };
+                assert_eq!(sizes(&replayed), sizes(fs), "{options:?}");
+            }
+        }
+    }
+
+    #[test]
+    fn test_generate() {
+        let fs = FileSystem::from_str(TEST_INPUT).unwrap();
+        assert_round_trip(&fs);
+
+        // the puzzle's own log is a depth first traversal
+        assert_eq!(fs.to_log(&LogOptions::default()), format!("{TEST_INPUT}\n"));
+
+        let bfs = fs.to_log(&LogOptions {
+            order: Order::BreadthFirst,
+            absolute: true,
+            ..Default::default()
+        });
+        let cds: Vec<_> = bfs.lines().filter(|l| l.starts_with("$ cd")).collect();
+        assert_eq!(cds, vec!["$ cd /", "$ cd /a", "$ cd /d", "$ cd /a/e"]);
+    }
+
+    #[test]
+    fn test_generate_from_path() {
+        let root = env::temp_dir().join(format!("day07-{}", std::process::id()));
+        std::fs::create_dir_all(root.join("a/b c")).unwrap();
+        std::fs::create_dir_all(root.join("empty")).unwrap();
+        std::fs::write(root.join("a/b c/my file.txt"), [0; 300]).unwrap();
+        std::fs::write(root.join("a/x.log"), [0; 20]).unwrap();
+        std::fs::write(root.join("top"), [0; 1]).unwrap();
+
+        let fs = FileSystem::from_path(&root);
+        std::fs::remove_dir_all(&root).unwrap();
+        let fs = fs.unwrap();
+
+        assert_eq!(fs[ROOT].size, 321);
+        assert_eq!(fs.lookup("/a/b c").map(|d| fs[d].size), Some(300));
+        assert_round_trip(&fs);
+    }
+
+    /// A complete binary tree of `n` directories, each but the root holding
+    /// one file.
+    fn binary_tree(n: usize, size: impl Fn(usize) -> usize) -> FileSystem {
+        let mut fs = FileSystem::new();
+        let mut dirs = vec![ROOT];
+        for i in 1..n {
+            let dir = fs.mkdir(dirs[(i - 1) / 2], &format!("d{i}")).unwrap();
+            fs.add_file(dir, "f", size(i)).unwrap();
+            dirs.push(dir);
+        }
+        fs
+    }
+
+    /// All sums freed by deleting directories up to and including `dir`.
+    fn all_sums(fs: &FileSystem, dir: Id) -> Vec<usize> {
+        let mut sums = vec![0];
+        for entry in &fs[dir].entries {
+            if let DirectoryEntry::Directory(d) = entry {
+                let child = all_sums(fs, *d);
+                sums = sums
+                    .iter()
+                    .flat_map(|a| child.iter().map(move |b| a + b))
+                    .collect();
+            }
+        }
+        sums.push(fs[dir].size);
+        sums
+    }
+
+    #[test]
+    fn test_plan_large() {
+        let is_ancestor = |fs: &FileSystem, a: Id, b: Id| {
+            std::iter::successors(fs[b].parent, |&d| fs[d].parent).any(|d| d == a)
+        };
+
+        let assert_valid = |fs: &FileSystem, needed| {
+            let deletion = fs.smallest_set_freeing(needed).unwrap();
+            let freed: usize = deletion.dirs.iter().map(|&d| fs[d].size).sum();
+            assert!(freed >= needed);
+            assert!(freed <= fs[fs.smallest_dir_freeing(needed).unwrap()].size);
+            for &a in &deletion.dirs {
+                assert!(deletion.dirs.iter().all(|&b| !is_ancestor(fs, a, b)));
+            }
+        };
+
+        // as many directories as a real input, with small files and with
+        // sizes in the millions
+        for fs in [
+            binary_tree(255, |i| i % 7 + 1),
+            binary_tree(255, |i| i * 7919 % 10007 * 1000 + i),
+        ] {
+            for needed in [fs[ROOT].size / 10, fs[ROOT].size / 3, fs[ROOT].size / 2 + 1] {
+                assert_valid(&fs, needed);
+            }
+        }
+
+        // few enough directories to try all plans
+        let fs = binary_tree(15, |i| i * 7919 % 10007 * 1000 + i);
+        let sums = all_sums(&fs, ROOT);
+        for needed in [1, 5_000_000, fs[ROOT].size / 3, fs[ROOT].size / 2] {
+            let best = sums.iter().filter(|&&s| s >= needed).min();
+            let deletion = fs.smallest_set_freeing(needed).unwrap();
+            assert!(deletion.optimal);
+            assert_eq!(
+                deletion.dirs.iter().map(|&d| fs[d].size).sum::<usize>(),
+                *best.unwrap()
+            );
+        }
     }
 }
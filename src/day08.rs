@@ -1,131 +1,437 @@
 use rustvent2022::get_input;
+use std::env;
+use std::error::Error;
+use std::fmt;
+use std::fs;
 
-fn parse(input: &str) -> Vec<Vec<u8>> {
-    let mut ret = Vec::new();
-    for line in input.lines() {
-        ret.push(line.chars().map(|c| c as u8 - '0' as u8).collect());
+/// A row of the forest, where `None` is a cell without a tree.
+type Row = Vec<Option<u32>>;
+
+#[derive(Debug, PartialEq)]
+enum ParseError {
+    Empty,
+    Ragged {
+        line: usize,
+        expected: usize,
+        found: usize,
+    },
+    Cell {
+        line: usize,
+        column: usize,
+        cell: String,
+    },
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ParseError::Empty => write!(f, "no trees in forest"),
+            ParseError::Ragged {
+                line,
+                expected,
+                found,
+            } => write!(f, "line {line}: expected {expected} cells, found {found}"),
+            ParseError::Cell { line, column, cell } => {
+                write!(
+                    f,
+                    "line {line}, column {column}: can't parse height '{cell}'"
+                )
+            }
+        }
     }
-    ret
 }
 
-fn part_two(input: &Vec<Vec<u8>>) -> usize {
-    let (len_x, len_y) = (input[0].len(), input.len());
+impl Error for ParseError {}
 
-    let mut best_scenic_score = 0;
+/// Parses rows of single digits, or of whitespace separated heights if any
+/// row contains whitespace. A `.` marks a cell without a tree. Blank lines
+/// are skipped.
+fn parse(input: &str) -> Result<Vec<Row>, ParseError> {
+    let separated = input
+        .lines()
+        .any(|l| l.trim().contains(char::is_whitespace));
 
-    // urgs this is going to be ugly this time...
-    for x in 0..len_x {
-        for y in 0..len_y {
-            let tree = input[y][x];
+    let mut forest: Vec<Row> = Vec::new();
+    for (n, line) in input.lines().enumerate() {
+        let cells: Vec<(usize, &str)> = if separated {
+            line.split_whitespace()
+                .map(|cell| (cell.as_ptr() as usize - line.as_ptr() as usize, cell))
+                .collect()
+        } else {
+            line.trim_end()
+                .char_indices()
+                .map(|(i, c)| (i, &line[i..i + c.len_utf8()]))
+                .collect()
+        };
+        if cells.is_empty() {
+            continue;
+        }
 
-            let mut vd_left = 0;
-            for x_c in (0..x).rev() {
-                vd_left += 1;
-                if input[y][x_c] >= tree {
-                    break;
+        let row = cells
+            .into_iter()
+            .map(|(i, cell)| {
+                match cell {
+                    "." => Ok(None),
+                    _ if !separated && cell.len() > 1 => Err(()),
+                    _ => cell.parse().map(Some).or(Err(())),
                 }
-            }
+                .or(Err(ParseError::Cell {
+                    line: n + 1,
+                    column: i + 1,
+                    cell: cell.to_string(),
+                }))
+            })
+            .collect::<Result<Row, _>>()?;
 
-            let mut vd_right = 0;
-            for x_c in x + 1..len_x {
-                vd_right += 1;
-                if input[y][x_c] >= tree {
-                    break;
-                }
+        if let Some(first) = forest.first() {
+            if first.len() != row.len() {
+                return Err(ParseError::Ragged {
+                    line: n + 1,
+                    expected: first.len(),
+                    found: row.len(),
+                });
             }
+        }
+        forest.push(row);
+    }
 
-            let mut vd_up = 0;
-            for y_c in (0..y).rev() {
-                vd_up += 1;
-                if input[y_c][x] >= tree {
-                    break;
-                }
-            }
+    if forest.is_empty() {
+        return Err(ParseError::Empty);
+    }
+    Ok(forest)
+}
 
-            let mut vd_down = 0;
-            for y_c in y + 1..len_y {
-                vd_down += 1;
-                if input[y_c][x] >= tree {
-                    break;
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Side {
+    Left,
+    Right,
+    Top,
+    Bottom,
+}
+
+impl Side {
+    const ALL: [Side; 4] = [Side::Left, Side::Right, Side::Top, Side::Bottom];
+
+    /// The lines of sight from this side, each listing positions `(x, y)`
+    /// starting at the edge.
+    fn lines(self, len_x: usize, len_y: usize) -> Vec<Vec<(usize, usize)>> {
+        match self {
+            Side::Left => (0..len_y)
+                .map(|y| (0..len_x).map(|x| (x, y)).collect())
+                .collect(),
+            Side::Right => (0..len_y)
+                .map(|y| (0..len_x).rev().map(|x| (x, y)).collect())
+                .collect(),
+            Side::Top => (0..len_x)
+                .map(|x| (0..len_y).map(|y| (x, y)).collect())
+                .collect(),
+            Side::Bottom => (0..len_x)
+                .map(|x| (0..len_y).rev().map(|y| (x, y)).collect())
+                .collect(),
+        }
+    }
+}
+
+/// For every tree, whether it can be seen from each side and how far it can
+/// see towards each side, indexed like the forest and then by `Side as usize`.
+/// Cells without a tree neither block nor end a view.
+#[derive(Debug)]
+struct Views {
+    visible: Vec<Vec<[bool; 4]>>,
+    distances: Vec<Vec<[usize; 4]>>,
+    trees: Vec<(usize, usize)>,
+}
+
+impl Views {
+    /// Sweeps every line of sight once with a stack of the trees still in
+    /// view, so that every tree is pushed and popped once per side.
+    fn of(forest: &[Row]) -> Self {
+        let (len_x, len_y) = (forest.first().map_or(0, Vec::len), forest.len());
+        let mut visible = vec![vec![[false; 4]; len_x]; len_y];
+        let mut distances = vec![vec![[0; 4]; len_x]; len_y];
+
+        for side in Side::ALL {
+            for line in side.lines(len_x, len_y) {
+                // indices into `line` and heights, the latter non-increasing
+                let mut stack: Vec<(usize, u32)> = Vec::new();
+                for (i, &(x, y)) in line.iter().enumerate() {
+                    let Some(tree) = forest[y][x] else {
+                        continue;
+                    };
+                    while stack.last().is_some_and(|&(_, h)| h < tree) {
+                        stack.pop();
+                    }
+                    visible[y][x][side as usize] = stack.is_empty();
+                    distances[y][x][side as usize] = i - stack.last().map_or(0, |&(j, _)| j);
+                    stack.push((i, tree));
                 }
             }
+        }
 
-            let sscore = vd_up * vd_down * vd_right * vd_left;
-            if sscore > best_scenic_score {
-                best_scenic_score = sscore;
-            }
+        let trees = (0..len_y)
+            .flat_map(|y| (0..len_x).map(move |x| (x, y)))
+            .filter(|&(x, y)| forest[y][x].is_some())
+            .collect();
+
+        Views {
+            visible,
+            distances,
+            trees,
         }
     }
-    best_scenic_score
+
+    fn is_visible(&self, x: usize, y: usize) -> bool {
+        self.visible[y][x].iter().any(|&v| v)
+    }
+
+    fn scenic_score(&self, x: usize, y: usize) -> usize {
+        self.distances[y][x].iter().product()
+    }
+
+    /// The positions of all trees in reading order.
+    fn positions(&self) -> impl Iterator<Item = (usize, usize)> + '_ {
+        self.trees.iter().copied()
+    }
 }
 
-fn part_one(input: &Vec<Vec<u8>>) -> usize {
-    let (len_x, len_y) = (input[0].len(), input.len());
+impl fmt::Display for Side {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            Side::Left => "left",
+            Side::Right => "right",
+            Side::Top => "top",
+            Side::Bottom => "bottom",
+        };
+        write!(f, "{name}")
+    }
+}
 
-    let mut vis_trees = 2 * len_x + 2 * len_y - 4;
+#[derive(Debug, PartialEq)]
+struct TreeReport {
+    x: usize,
+    y: usize,
+    height: u32,
+    visible_from: Vec<Side>,
+    distances: [usize; 4],
+    score: usize,
+}
 
-    // urgs this is going to be ugly this time...
-    for x in 1..len_x - 1 {
-        for y in 1..len_y - 1 {
-            let tree = input[y][x];
+impl fmt::Display for TreeReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "({}, {}) height {}: ", self.x, self.y, self.height)?;
+        if self.visible_from.is_empty() {
+            write!(f, "hidden")?;
+        } else {
+            let sides: Vec<_> = self.visible_from.iter().map(Side::to_string).collect();
+            write!(f, "visible from {}", sides.join(", "))?;
+        }
+        let distances: Vec<_> = Side::ALL
+            .iter()
+            .zip(self.distances)
+            .map(|(side, d)| format!("{side} {d}"))
+            .collect();
+        write!(
+            f,
+            "; distances {}; score {}",
+            distances.join(", "),
+            self.score
+        )
+    }
+}
 
-            let mut vis = true;
-            for x_c in 0..x {
-                if input[y][x_c] >= tree {
-                    vis = false;
-                    break;
-                }
-            }
-            if vis {
-                vis_trees += 1;
-                continue;
-            }
+fn report(forest: &[Row]) -> Vec<TreeReport> {
+    let views = Views::of(forest);
+    views
+        .positions()
+        .map(|(x, y)| TreeReport {
+            x,
+            y,
+            height: forest[y][x].unwrap_or_default(),
+            visible_from: Side::ALL
+                .into_iter()
+                .filter(|&side| views.visible[y][x][side as usize])
+                .collect(),
+            distances: views.distances[y][x],
+            score: views.scenic_score(x, y),
+        })
+        .collect()
+}
 
-            vis = true;
-            for x_c in x + 1..len_x {
-                if input[y][x_c] >= tree {
-                    vis = false;
-                    break;
-                }
-            }
-            if vis {
-                vis_trees += 1;
-                continue;
-            }
+/// The `k` trees with the best scenic score, best first and ties in reading
+/// order.
+fn top(forest: &[Row], k: usize) -> Vec<TreeReport> {
+    let mut trees = report(forest);
+    trees.sort_by_key(|t| (std::cmp::Reverse(t.score), t.y, t.x));
+    trees.truncate(k);
+    trees
+}
 
-            vis = true;
-            for y_c in 0..y {
-                if input[y_c][x] >= tree {
-                    vis = false;
-                    break;
-                }
-            }
-            if vis {
-                vis_trees += 1;
-                continue;
-            }
+/// Visible trees show their height, or `+` if it has more than one digit,
+/// hidden ones a `.` and cells without a tree a space.
+fn render_visible(forest: &[Row]) -> String {
+    let views = Views::of(forest);
+    let mut out = String::new();
+    for (y, row) in forest.iter().enumerate() {
+        for (x, height) in row.iter().enumerate() {
+            out.push(match height {
+                None => ' ',
+                Some(h) if views.is_visible(x, y) => char::from_digit(*h, 10).unwrap_or('+'),
+                Some(_) => '.',
+            });
+        }
+        out.push('\n');
+    }
+    out
+}
 
-            vis = true;
-            for y_c in y + 1..len_y {
-                if input[y_c][x] >= tree {
-                    vis = false;
-                    break;
-                }
-            }
-            if vis {
-                vis_trees += 1;
-                continue;
-            }
+/// Scenic scores relative to the best one, from 0 (black) to 255 (white).
+fn scenic_levels(forest: &[Row]) -> Vec<Vec<u8>> {
+    let views = Views::of(forest);
+    let best = part_two(forest).max(1);
+    forest
+        .iter()
+        .enumerate()
+        .map(|(y, row)| {
+            (0..row.len())
+                .map(|x| (views.scenic_score(x, y) * 255 / best) as u8)
+                .collect()
+        })
+        .collect()
+}
+
+const SHADES: &[u8] = b" .:-=+*#%@";
+
+fn render_heatmap(forest: &[Row]) -> String {
+    let mut out = String::new();
+    for row in scenic_levels(forest) {
+        for level in row {
+            let shade = level as usize * (SHADES.len() - 1) / 255;
+            out.push(char::from(SHADES[shade]));
+        }
+        out.push('\n');
+    }
+    out
+}
+
+fn crc32(bytes: &[u8]) -> u32 {
+    let mut crc = !0u32;
+    for &b in bytes {
+        crc ^= b as u32;
+        for _ in 0..8 {
+            crc = if crc & 1 == 1 {
+                (crc >> 1) ^ 0xEDB8_8320
+            } else {
+                crc >> 1
+            };
         }
     }
+    !crc
+}
 
-    vis_trees
+fn adler32(bytes: &[u8]) -> u32 {
+    let (mut a, mut b) = (1u32, 0u32);
+    for &byte in bytes {
+        a = (a + byte as u32) % 65521;
+        b = (b + a) % 65521;
+    }
+    (b << 16) | a
+}
+
+/// Encodes an 8 bit grayscale image as PNG, storing the pixels uncompressed
+/// so that no deflate implementation is needed.
+fn encode_png(pixels: &[Vec<u8>]) -> Vec<u8> {
+    let (width, height) = (pixels.first().map_or(0, Vec::len), pixels.len());
+
+    // every scanline is prefixed by filter type 0
+    let raw: Vec<u8> = pixels
+        .iter()
+        .flat_map(|row| std::iter::once(0).chain(row.iter().copied()))
+        .collect();
+    let mut zlib = vec![0x78, 0x01];
+    let blocks: Vec<_> = raw.chunks(0xFFFF).collect();
+    if blocks.is_empty() {
+        zlib.extend([1, 0, 0, 0xFF, 0xFF]);
+    }
+    for (i, block) in blocks.iter().enumerate() {
+        zlib.push((i + 1 == blocks.len()) as u8);
+        let len = block.len() as u16;
+        zlib.extend(len.to_le_bytes());
+        zlib.extend((!len).to_le_bytes());
+        zlib.extend(*block);
+    }
+    zlib.extend(adler32(&raw).to_be_bytes());
+
+    let mut header = Vec::new();
+    header.extend((width as u32).to_be_bytes());
+    header.extend((height as u32).to_be_bytes());
+    header.extend([8, 0, 0, 0, 0]);
+
+    let mut png = b"\x89PNG\r\n\x1a\n".to_vec();
+    for (kind, data) in [(b"IHDR", header), (b"IDAT", zlib), (b"IEND", Vec::new())] {
+        png.extend((data.len() as u32).to_be_bytes());
+        let start = png.len();
+        png.extend(kind);
+        png.extend(data);
+        let crc = crc32(&png[start..]);
+        png.extend(crc.to_be_bytes());
+    }
+    png
+}
+
+/// The scenic heatmap as PNG, every tree drawn as a `scale` pixel square.
+fn heatmap_png(forest: &[Row], scale: usize) -> Vec<u8> {
+    let pixels: Vec<Vec<u8>> = scenic_levels(forest)
+        .iter()
+        .flat_map(|row| {
+            let line: Vec<u8> = row
+                .iter()
+                .flat_map(|&level| std::iter::repeat_n(level, scale))
+                .collect();
+            std::iter::repeat_n(line, scale)
+        })
+        .collect();
+    encode_png(&pixels)
+}
+
+fn part_two(input: &[Row]) -> usize {
+    let views = Views::of(input);
+    views
+        .positions()
+        .map(|(x, y)| views.scenic_score(x, y))
+        .max()
+        .unwrap_or(0)
+}
+
+fn part_one(input: &[Row]) -> usize {
+    let views = Views::of(input);
+    views
+        .positions()
+        .filter(|&(x, y)| views.is_visible(x, y))
+        .count()
 }
 
 fn main() -> Result<(), Box<dyn std::error::Error + 'static>> {
-    let input = parse(&get_input("2022", "8"));
+    let input = parse(&get_input("2022", "8"))?;
     println!("Solution part one: {}", part_one(&input));
     println!("Solution part two: {}", part_two(&input));
+
+    for arg in env::args().skip(1) {
+        if arg == "--visible" {
+            print!("{}", render_visible(&input));
+        } else if arg == "--heatmap" {
+            print!("{}", render_heatmap(&input));
+        } else if arg == "--report" {
+            for tree in report(&input) {
+                println!("{tree}");
+            }
+        } else if let Some(k) = arg.strip_prefix("--top=") {
+            for tree in top(&input, k.parse()?) {
+                println!("{tree}");
+            }
+        } else if let Some(path) = arg.strip_prefix("--png=") {
+            fs::write(path, heatmap_png(&input, 4))?;
+        }
+    }
     Ok(())
 }
 
@@ -141,22 +447,97 @@ mod tests {
 
     #[test]
     fn test_parse() {
-        let input = parse(TEST);
+        let input = parse(TEST).unwrap();
 
-        assert_eq!(input[1][1], 5);
+        assert_eq!(input[1][1], Some(5));
     }
 
     #[test]
     fn test_part_two() {
-        let input = parse(TEST);
+        let input = parse(TEST).unwrap();
 
         assert_eq!(part_two(&input), 8);
     }
 
     #[test]
     fn test_part_one() {
-        let input = parse(TEST);
+        let input = parse(TEST).unwrap();
 
         assert_eq!(part_one(&input), 21);
     }
+
+    #[test]
+    fn test_views() {
+        let views = Views::of(&parse(TEST).unwrap());
+
+        assert_eq!(views.visible[1][1], [true, false, true, false]);
+        assert_eq!(views.visible[2][2], [false, false, false, false]);
+        assert_eq!(views.distances[1][2], [1, 2, 1, 2]);
+        assert_eq!(views.distances[3][2], [2, 2, 2, 1]);
+        assert_eq!(views.distances[0][0], [0, 2, 0, 2]);
+        assert_eq!(views.scenic_score(2, 3), 8);
+    }
+
+    #[test]
+    fn test_report() {
+        let input = parse(TEST).unwrap();
+        let best = top(&input, 2);
+
+        assert_eq!(
+            best[0].to_string(),
+            "(2, 3) height 5: visible from left, bottom; \
+             distances left 2, right 2, top 2, bottom 1; score 8"
+        );
+        assert_eq!((best[1].x, best[1].y, best[1].score), (1, 2, 6));
+        assert_eq!(report(&input).len(), 25);
+
+        assert_eq!(
+            render_visible(&input),
+            "30373\n255.2\n65.32\n3.5.9\n35390\n"
+        );
+        assert_eq!(render_heatmap(&input).lines().nth(3), Some(" .@- "));
+    }
+
+    #[test]
+    fn test_png() {
+        assert_eq!(adler32(b"Wikipedia"), 0x11E6_0398);
+
+        let png = heatmap_png(&parse(TEST).unwrap(), 2);
+        assert_eq!(&png[..8], b"\x89PNG\r\n\x1a\n");
+        // width and height in IHDR
+        assert_eq!(&png[16..24], &[0, 0, 0, 10, 0, 0, 0, 10]);
+        // IEND with its well-known checksum
+        assert_eq!(&png[png.len() - 12..], b"\0\0\0\0IEND\xAE\x42\x60\x82");
+    }
+
+    #[test]
+    fn test_masked() {
+        let input = parse("30373\n25.12\n65332\n").unwrap();
+        let views = Views::of(&input);
+
+        assert_eq!(part_one(&input), 13);
+        assert_eq!(views.distances[1][1], [1, 3, 1, 1]);
+        assert_eq!(views.distances[1][3], [2, 1, 1, 1]);
+        assert_eq!(render_visible(&input), "30373\n25 .2\n65332\n");
+
+        let input = parse("12 . 3 .\n\n 100 4 . .\n1 2 3 5").unwrap();
+        assert_eq!(input[1], vec![Some(100), Some(4), None, None]);
+        assert_eq!(part_two(&input), 2);
+    }
+
+    #[test]
+    fn test_parse_errors() {
+        let error = |s| parse(s).unwrap_err().to_string();
+
+        assert_eq!(error("123\n45\n"), "line 2: expected 3 cells, found 2");
+        assert_eq!(
+            error("123\n4x6\n"),
+            "line 2, column 2: can't parse height 'x'"
+        );
+        assert_eq!(
+            error("1 2\n3 -4"),
+            "line 2, column 3: can't parse height '-4'"
+        );
+        assert_eq!(error("\n\n"), "no trees in forest");
+    }
 }
@@ -1,6 +1,8 @@
 use rustvent2022::get_input;
-use std::collections::HashSet;
-use std::str::FromStr;
+use std::collections::{HashMap, HashSet};
+use std::env;
+use std::error::Error;
+use std::fmt;
 
 #[derive(Debug, PartialEq)]
 enum Direction {
@@ -20,149 +22,434 @@ struct Instruction {
     len: usize,
 }
 
-impl FromStr for Instruction {
-    type Err = &'static str;
+#[derive(Debug, PartialEq)]
+struct ParseError {
+    /// Counting from 1, like the column.
+    line: usize,
+    column: usize,
+    message: &'static str,
+}
 
-    fn from_str(s: &str) -> Result<Self, Self::Err> {
-        let mut parts = s.split(" ");
-        let dir = parts.next().ok_or("No Direction")?;
-        let number: usize = parts
-            .next()
-            .ok_or("No Number")?
-            .parse()
-            .or(Err("Can't parse number"))?;
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "line {}, column {}: {}",
+            self.line, self.column, self.message
+        )
+    }
+}
 
-        Ok(match dir {
-            "R" => Instruction {
-                dir: Direction::R,
-                len: number,
-            },
-            "L" => Instruction {
-                dir: Direction::L,
-                len: number,
-            },
-            "U" => Instruction {
-                dir: Direction::U,
-                len: number,
-            },
-            "D" => Instruction {
-                dir: Direction::D,
-                len: number,
-            },
-            _ => Err("Can't parse direction")?,
-        })
+impl Error for ParseError {}
+
+/// A rope needs at least its head.
+#[derive(Debug, PartialEq)]
+struct EmptyRope;
+
+impl fmt::Display for EmptyRope {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "a rope needs at least one knot")
+    }
+}
+
+impl Error for EmptyRope {}
+
+/// Parses a line of `<direction> <steps>`, where the direction may also be
+/// diagonal (`UR`, `UL`, `DR`, `DL`), or a vector move `V <dx> <dy>`. A vector
+/// move becomes a diagonal move followed by a straight one.
+fn parse_line(line: &str, n: usize) -> Result<Vec<Instruction>, ParseError> {
+    let mut tokens = Vec::new();
+    let mut start = None;
+    for (i, c) in line.char_indices().chain([(line.len(), ' ')]) {
+        match (c.is_whitespace(), start) {
+            (true, Some(s)) => {
+                tokens.push((s + 1, &line[s..i]));
+                start = None;
+            }
+            (false, None) => start = Some(i),
+            _ => (),
+        }
+    }
+    let error = |column, message| ParseError {
+        line: n,
+        column,
+        message,
+    };
+    let end = line.len() + 1;
+    let mut tokens = tokens.into_iter();
+    let (column, dir) = tokens.next().ok_or(error(1, "no direction"))?;
+    let mut number = |what| {
+        let (column, token): (usize, &str) = tokens.next().ok_or(error(end, what))?;
+        token
+            .parse::<isize>()
+            .or(Err(error(column, "can't parse number")))
+            .map(|n| (column, n))
+    };
+
+    let instructions = match dir {
+        "V" => {
+            let (_, dx) = number("no x in vector")?;
+            let (_, dy) = number("no y in vector")?;
+            let diagonal = dx.abs().min(dy.abs());
+            let straight = (dx.abs() - diagonal, dy.abs() - diagonal);
+            [
+                (dx.signum(), dy.signum(), diagonal),
+                (dx.signum(), 0, straight.0),
+                (0, dy.signum(), straight.1),
+            ]
+            .into_iter()
+            .filter(|&(_, _, len)| len > 0)
+            .filter_map(|(dx, dy, len)| {
+                Some(Instruction {
+                    dir: Direction::from_delta(dx, dy)?,
+                    len: len as usize,
+                })
+            })
+            .collect()
+        }
+        _ => {
+            let dir = match dir {
+                "R" => Direction::R,
+                "L" => Direction::L,
+                "U" => Direction::U,
+                "D" => Direction::D,
+                "UR" => Direction::UR,
+                "UL" => Direction::UL,
+                "DR" => Direction::DR,
+                "DL" => Direction::DL,
+                _ => return Err(error(column, "can't parse direction")),
+            };
+            let (column, len) = number("no number")?;
+            let len = usize::try_from(len).or(Err(error(column, "negative number")))?;
+            vec![Instruction { dir, len }]
+        }
+    };
+
+    match tokens.next() {
+        Some((column, _)) => Err(error(column, "unexpected text")),
+        None => Ok(instructions),
     }
 }
 
-#[derive(Clone)]
-struct Marker {
-    x: isize,
-    y: isize,
-    visited: HashSet<(isize, isize)>,
+impl fmt::Display for Direction {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{:?}", self)
+    }
 }
 
-impl Marker {
-    fn new() -> Self {
-        Marker {
-            x: 0,
-            y: 0,
-            visited: HashSet::from([(0, 0)]),
+impl fmt::Display for Instruction {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} {}", self.dir, self.len)
+    }
+}
+
+impl Direction {
+    fn delta(&self) -> (isize, isize) {
+        match self {
+            Direction::R => (1, 0),
+            Direction::L => (-1, 0),
+            Direction::U => (0, 1),
+            Direction::D => (0, -1),
+            Direction::UR => (1, 1),
+            Direction::UL => (-1, 1),
+            Direction::DR => (1, -1),
+            Direction::DL => (-1, -1),
         }
     }
 
-    fn follow(&mut self, other: &Marker) {
-        if (self.x - other.x).abs() > 1 && self.y == other.y {
-            if self.x < other.x {
-                self.move_dir(&Direction::R);
-            } else {
-                self.move_dir(&Direction::L);
-            }
-        } else if (self.y - other.y).abs() > 1 && self.x == other.x {
-            if self.y < other.y {
-                self.move_dir(&Direction::U);
-            } else {
-                self.move_dir(&Direction::D);
-            }
-        } else if (self.y - other.y).abs() > 1 || (self.x - other.x).abs() > 1 {
-            if self.x < other.x && self.y < other.y {
-                self.move_dir(&Direction::UR);
-            } else if self.x < other.x && self.y > other.y {
-                self.move_dir(&Direction::DR);
-            } else if self.x > other.x && self.y < other.y {
-                self.move_dir(&Direction::UL);
-            } else if self.x > other.x && self.y > other.y {
-                self.move_dir(&Direction::DL);
-            } else {
-                panic!("Not a valid position");
-            }
+    /// The direction of a step by `(dx, dy)`, both in -1..=1.
+    fn from_delta(dx: isize, dy: isize) -> Option<Self> {
+        match (dx, dy) {
+            (1, 0) => Some(Direction::R),
+            (-1, 0) => Some(Direction::L),
+            (0, 1) => Some(Direction::U),
+            (0, -1) => Some(Direction::D),
+            (1, 1) => Some(Direction::UR),
+            (-1, 1) => Some(Direction::UL),
+            (1, -1) => Some(Direction::DR),
+            (-1, -1) => Some(Direction::DL),
+            _ => None,
         }
     }
+}
+
+/// Decides how a knot follows the knot in front of it.
+trait FollowRule {
+    /// Where a knot moves if the knot in front is `(dx, dy)` away, if at all.
+    fn step(&self, dx: isize, dy: isize) -> Option<Direction>;
+}
 
-    fn move_dir(&mut self, dir: &Direction) {
-        match dir {
-            Direction::R => self.x += 1,
-            Direction::L => self.x -= 1,
-            Direction::U => self.y += 1,
-            Direction::D => self.y -= 1,
-            Direction::UR => {
-                self.x += 1;
-                self.y += 1;
+/// The puzzle: knots touch if they are adjacent, diagonals included.
+/// Otherwise the knot moves one step towards the one in front, diagonally if
+/// they are not in the same row or column.
+struct Chebyshev;
+
+/// Knots only touch if they are adjacent without diagonals, and only move
+/// along rows and columns.
+struct FourConnected;
+
+/// Like `Chebyshev`, but knots touch up to a distance of `slack`.
+struct Slack(isize);
+
+impl FollowRule for Slack {
+    fn step(&self, dx: isize, dy: isize) -> Option<Direction> {
+        if dx.abs() > self.0 || dy.abs() > self.0 {
+            Direction::from_delta(dx.signum(), dy.signum())
+        } else {
+            None
+        }
+    }
+}
+
+impl FollowRule for Chebyshev {
+    fn step(&self, dx: isize, dy: isize) -> Option<Direction> {
+        Slack(1).step(dx, dy)
+    }
+}
+
+impl FollowRule for FourConnected {
+    fn step(&self, dx: isize, dy: isize) -> Option<Direction> {
+        if dx.abs() + dy.abs() <= 1 {
+            None
+        } else if dx.abs() >= dy.abs() {
+            Direction::from_delta(dx.signum(), 0)
+        } else {
+            Direction::from_delta(0, dy.signum())
+        }
+    }
+}
+
+struct Rope<F> {
+    /// The head comes first.
+    knots: Vec<(isize, isize)>,
+    rule: F,
+    /// The cells visited by every tracked knot.
+    visited: HashMap<usize, HashSet<(isize, isize)>>,
+}
+
+impl<F: FollowRule> Rope<F> {
+    /// A rope of `len` knots, all at the origin, remembering the cells
+    /// visited by the knots at the indices in `tracked`.
+    fn new(len: usize, rule: F, tracked: &[usize]) -> Result<Self, EmptyRope> {
+        if len == 0 {
+            return Err(EmptyRope);
+        }
+        Ok(Rope {
+            knots: vec![(0, 0); len],
+            rule,
+            visited: tracked
+                .iter()
+                .map(|&i| (i, HashSet::from([(0, 0)])))
+                .collect(),
+        })
+    }
+
+    fn move_head(&mut self, dir: &Direction) {
+        let (dx, dy) = dir.delta();
+        self.knots[0].0 += dx;
+        self.knots[0].1 += dy;
+        if let Some(visited) = self.visited.get_mut(&0) {
+            visited.insert(self.knots[0]);
+        }
+
+        for i in 1..self.knots.len() {
+            let (front, knot) = (self.knots[i - 1], self.knots[i]);
+            let Some(dir) = self.rule.step(front.0 - knot.0, front.1 - knot.1) else {
+                continue;
+            };
+            let (dx, dy) = dir.delta();
+            self.knots[i] = (knot.0 + dx, knot.1 + dy);
+            if let Some(visited) = self.visited.get_mut(&i) {
+                visited.insert(self.knots[i]);
             }
-            Direction::UL => {
-                self.x -= 1;
-                self.y += 1;
+        }
+    }
+
+    fn run(&mut self, input: &[Instruction]) {
+        for instr in input {
+            for _ in 0..instr.len {
+                self.move_head(&instr.dir);
             }
-            Direction::DR => {
-                self.y -= 1;
-                self.x += 1;
+        }
+    }
+
+    fn visited(&self, knot: usize) -> Option<&HashSet<(isize, isize)>> {
+        self.visited.get(&knot)
+    }
+
+    /// Runs `input`, taking a snapshot of the knots at the start and after
+    /// every instruction, or after every single step for an animation.
+    fn record(&mut self, input: &[Instruction], every_step: bool) -> Vec<Frame> {
+        let mut frames = vec![Frame {
+            title: String::from("Initial State"),
+            knots: self.knots.clone(),
+        }];
+
+        for instr in input {
+            for step in 1..=instr.len {
+                self.move_head(&instr.dir);
+                if every_step {
+                    frames.push(Frame {
+                        title: format!("{instr} ({step}/{})", instr.len),
+                        knots: self.knots.clone(),
+                    });
+                }
             }
-            Direction::DL => {
-                self.y -= 1;
-                self.x -= 1;
+            if !every_step {
+                frames.push(Frame {
+                    title: instr.to_string(),
+                    knots: self.knots.clone(),
+                });
             }
         }
-        self.visited.insert((self.x, self.y));
+        frames
     }
 }
 
-fn parse(input: &str) -> Vec<Instruction> {
-    input
-        .lines()
-        .map(|l| Instruction::from_str(l).unwrap())
-        .collect()
+struct Frame {
+    title: String,
+    knots: Vec<(isize, isize)>,
 }
 
-fn part_one(input: &Vec<Instruction>) -> usize {
-    let mut h = Marker::new();
-    let mut t = Marker::new();
+/// The smallest rectangle around some cells and the start.
+struct Bounds {
+    min: (isize, isize),
+    max: (isize, isize),
+}
 
-    for instr in input {
-        for _ in 0..instr.len {
-            h.move_dir(&instr.dir);
-            t.follow(&h);
+impl Bounds {
+    fn around<'a>(cells: impl IntoIterator<Item = &'a (isize, isize)>) -> Self {
+        let mut bounds = Bounds {
+            min: (0, 0),
+            max: (0, 0),
+        };
+        for &(x, y) in cells {
+            bounds.min = (bounds.min.0.min(x), bounds.min.1.min(y));
+            bounds.max = (bounds.max.0.max(x), bounds.max.1.max(y));
         }
+        bounds
+    }
+
+    /// Draws every cell, up is up.
+    fn draw(&self, cell: impl Fn((isize, isize)) -> char) -> String {
+        let mut s = String::new();
+        for y in (self.min.1..=self.max.1).rev() {
+            s.extend((self.min.0..=self.max.0).map(|x| cell((x, y))));
+            s.push('\n');
+        }
+        s
     }
-    t.visited.len()
 }
 
-fn part_two(input: &Vec<Instruction>) -> usize {
-    let mut rope = vec![Marker::new(); 10];
+/// `H` for the head, then `1`, `2`, ... or `T` if there is only a tail.
+fn knot_label(i: usize, len: usize) -> char {
+    match i {
+        0 => 'H',
+        _ if len == 2 => 'T',
+        i => char::from_digit(i as u32, 36).unwrap_or('*'),
+    }
+}
 
-    for instr in input {
-        for _ in 0..instr.len {
-            rope[0].move_dir(&instr.dir);
-            for i in 1..rope.len() {
-                let par = rope[i - 1].clone();
-                rope[i].follow(&par);
-            }
+/// The knots in front cover the ones behind, `s` marks the start.
+fn render_knots(knots: &[(isize, isize)], bounds: &Bounds) -> String {
+    bounds.draw(|cell| match knots.iter().position(|&k| k == cell) {
+        Some(i) => knot_label(i, knots.len()),
+        None if cell == (0, 0) => 's',
+        None => '.',
+    })
+}
+
+fn render_visited(visited: &HashSet<(isize, isize)>, bounds: &Bounds) -> String {
+    bounds.draw(|cell| match cell {
+        (0, 0) => 's',
+        c if visited.contains(&c) => '#',
+        _ => '.',
+    })
+}
+
+/// The rope after every instruction (or step) and the cells visited by its
+/// tail, all drawn within the same bounds.
+fn render<F: FollowRule>(
+    input: &[Instruction],
+    len: usize,
+    rule: F,
+    every_step: bool,
+) -> Result<String, EmptyRope> {
+    let mut rope = Rope::new(len, rule, &[len.saturating_sub(1)])?;
+    let frames = rope.record(input, every_step);
+    let bounds = Bounds::around(frames.iter().flat_map(|f| &f.knots));
+
+    let mut s = String::new();
+    for frame in &frames {
+        s.push_str(&format!("== {} ==\n", frame.title));
+        s.push_str(&render_knots(&frame.knots, &bounds));
+        s.push('\n');
+    }
+    if let Some(visited) = rope.visited(len - 1) {
+        s.push_str("== Visited by the tail ==\n");
+        s.push_str(&render_visited(visited, &bounds));
+    }
+    Ok(s)
+}
+
+fn parse(input: &str) -> Result<Vec<Instruction>, ParseError> {
+    let mut instructions = Vec::new();
+    for (n, line) in input.lines().enumerate() {
+        if !line.trim().is_empty() {
+            instructions.extend(parse_line(line, n + 1)?);
         }
     }
-    rope.last().unwrap().visited.len()
+    Ok(instructions)
+}
+
+/// The number of cells the tail of a rope with `len` knots visits.
+fn tail_visits<F: FollowRule>(
+    input: &[Instruction],
+    len: usize,
+    rule: F,
+) -> Result<usize, EmptyRope> {
+    let tail = len.saturating_sub(1);
+    let mut rope = Rope::new(len, rule, &[tail])?;
+    rope.run(input);
+    Ok(rope.visited(tail).map_or(0, |v| v.len()))
+}
+
+fn part_one(input: &[Instruction]) -> Result<usize, EmptyRope> {
+    tail_visits(input, 2, Chebyshev)
+}
+
+fn part_two(input: &[Instruction]) -> Result<usize, EmptyRope> {
+    tail_visits(input, 10, Chebyshev)
 }
+
 fn main() -> Result<(), Box<dyn std::error::Error + 'static>> {
-    let input = parse(&get_input("2022", "9"));
-    println!("Solution part one: {}", part_one(&input));
-    println!("Solution part two: {}", part_two(&input));
+    let input = parse(&get_input("2022", "9"))?;
+    println!("Solution part one: {}", part_one(&input)?);
+    println!("Solution part two: {}", part_two(&input)?);
+
+    let args: Vec<String> = env::args().collect();
+    if args.iter().any(|a| a == "--render") {
+        print!("{}", render(&input, 10, Chebyshev, false)?);
+    }
+    if args.iter().any(|a| a == "--animate") {
+        print!("{}", render(&input, 10, Chebyshev, true)?);
+    }
+
+    // variants, e.g. `--knots=5 --rule=slack2`
+    let knots = args.iter().find_map(|a| a.strip_prefix("--knots="));
+    let rule = args.iter().find_map(|a| a.strip_prefix("--rule="));
+    if knots.is_some() || rule.is_some() {
+        let len: usize = knots.unwrap_or("10").parse()?;
+        let visits = match rule.unwrap_or("chebyshev") {
+            "chebyshev" => tail_visits(&input, len, Chebyshev)?,
+            "four" => tail_visits(&input, len, FourConnected)?,
+            r => match r.strip_prefix("slack") {
+                Some(slack) => tail_visits(&input, len, Slack(slack.parse()?))?,
+                None => return Err(format!("unknown follow rule {r}").into()),
+            },
+        };
+        println!("Cells visited by the tail: {visits}");
+    }
     Ok(())
 }
 
@@ -181,7 +468,7 @@ R 2";
 
     #[test]
     fn test_parse() {
-        let input = parse(TEST);
+        let input = parse(TEST).unwrap();
 
         assert_eq!(
             input[4],
@@ -194,9 +481,9 @@ R 2";
 
     #[test]
     fn test_part_one() {
-        let input = parse(TEST);
+        let input = parse(TEST).unwrap();
 
-        assert_eq!(part_one(&input), 13);
+        assert_eq!(part_one(&input).unwrap(), 13);
     }
 
     const TEST_TWO: &str = r"R 5
@@ -210,8 +497,100 @@ U 20";
 
     #[test]
     fn test_part_two() {
-        let input = parse(TEST_TWO);
+        let input = parse(TEST_TWO).unwrap();
+
+        assert_eq!(part_two(&input).unwrap(), 36);
+    }
+
+    #[test]
+    fn test_follow_rules() {
+        let input = parse(TEST_TWO).unwrap();
+
+        assert_eq!(tail_visits(&input, 10, Slack(1)), Ok(36));
+        assert_eq!(tail_visits(&input, 10, FourConnected), Ok(31));
+        assert_eq!(tail_visits(&input, 10, Slack(2)), Ok(6));
+
+        let mut rope = Rope::new(10, Chebyshev, &[1, 9]).unwrap();
+        rope.run(&input);
+        assert_eq!(rope.visited(9).unwrap().len(), 36);
+        assert_eq!(rope.visited(1).unwrap().len(), 88);
+        assert!(rope.visited(5).is_none());
+    }
+
+    #[test]
+    fn test_short_ropes() {
+        let input = parse("R 4\nU 4").unwrap();
+
+        // the head alone is also the tail
+        assert_eq!(tail_visits(&input, 1, Chebyshev), Ok(9));
+        assert_eq!(tail_visits(&input, 0, Chebyshev), Err(EmptyRope));
+        assert!(render(&input, 0, Chebyshev, false).is_err());
+    }
+
+    #[test]
+    fn test_render() {
+        let input = parse(TEST).unwrap();
+        let s = render(&input, 2, Chebyshev, false).unwrap();
+
+        assert!(s.starts_with("== Initial State ==\n......\n......\n......\n......\nH.....\n"));
+        assert!(s.ends_with(
+            "== R 2 ==
+......
+......
+.TH...
+......
+s.....
+
+== Visited by the tail ==
+..##..
+...##.
+.####.
+....#.
+s###..
+"
+        ));
+
+        let s = render(&parse(TEST_TWO).unwrap(), 10, Chebyshev, true).unwrap();
+        assert!(s.contains("== R 17 (17/17) ==\n"));
+        assert!(s.contains("\n...........s.........98765\n"));
+        assert_eq!(s.matches("==\n").count(), 1 + 96 + 1);
+    }
+
+    #[test]
+    fn test_extended_moves() {
+        let input = parse("UR 2\nV 3 -5\n\nV 0 0\nDL 1").unwrap();
+        assert_eq!(
+            input,
+            vec![
+                Instruction {
+                    dir: Direction::UR,
+                    len: 2
+                },
+                Instruction {
+                    dir: Direction::DR,
+                    len: 3
+                },
+                Instruction {
+                    dir: Direction::D,
+                    len: 2
+                },
+                Instruction {
+                    dir: Direction::DL,
+                    len: 1
+                },
+            ]
+        );
+
+        let mut rope = Rope::new(3, Chebyshev, &[2]).unwrap();
+        rope.run(&input);
+        assert_eq!(rope.knots, vec![(4, -4), (4, -3), (4, -2)]);
+        assert_eq!(part_one(&input).unwrap(), 7);
 
-        assert_eq!(part_two(&input), 36);
+        let error = |s| parse(s).unwrap_err().to_string();
+        assert_eq!(error("R 4\nX 1"), "line 2, column 1: can't parse direction");
+        assert_eq!(error("R 4\n  U x"), "line 2, column 5: can't parse number");
+        assert_eq!(error("V 3"), "line 1, column 4: no y in vector");
+        assert_eq!(error("L -2"), "line 1, column 3: negative number");
+        assert_eq!(error("D 2 3"), "line 1, column 5: unexpected text");
     }
 }
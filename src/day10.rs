@@ -1,9 +1,14 @@
-use rustvent2022::get_input;
+use rustvent2022::{get_input, ocr};
+use std::collections::HashMap;
+use std::env;
 use std::error::Error;
 use std::fmt;
+use std::fs;
+use std::io::{self, BufRead, Write};
+use std::iter::Peekable;
 use std::str::FromStr;
 
-#[derive(Debug)]
+#[derive(Debug, PartialEq)]
 enum MyError {
     Generic(&'static str),
 }
@@ -24,108 +29,787 @@ enum Instruction {
     Noop,
 }
 
+#[derive(Debug, PartialEq)]
+enum AsmError {
+    Empty,
+    UnknownMnemonic(String),
+    MissingOperand(&'static str),
+    BadOperand(String),
+    ExtraOperand(String),
+    BadLabel(String),
+    DuplicateLabel(String),
+    /// The encoding ends within an instruction starting at the given byte.
+    Truncated(usize),
+    UnknownOpcode(usize, u8),
+}
+
+impl fmt::Display for AsmError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            AsmError::Empty => write!(f, "empty instruction"),
+            AsmError::UnknownMnemonic(m) => write!(f, "unknown instruction `{m}`"),
+            AsmError::MissingOperand(m) => write!(f, "`{m}` needs an operand"),
+            AsmError::BadOperand(o) => write!(f, "`{o}` is not a number"),
+            AsmError::ExtraOperand(o) => write!(f, "unexpected operand `{o}`"),
+            AsmError::BadLabel(l) => write!(f, "`{l}` is not a valid label"),
+            AsmError::DuplicateLabel(l) => write!(f, "label `{l}` is defined twice"),
+            AsmError::Truncated(at) => write!(f, "instruction at byte {at} is cut off"),
+            AsmError::UnknownOpcode(at, op) => write!(f, "unknown opcode {op} at byte {at}"),
+        }
+    }
+}
+
+impl Error for AsmError {}
+
+/// An `AsmError` in a line of the assembly, counting from 1.
+#[derive(Debug, PartialEq)]
+struct Diagnostic {
+    line: usize,
+    error: AsmError,
+}
+
+impl fmt::Display for Diagnostic {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "line {}: {}", self.line, self.error)
+    }
+}
+
+impl Error for Diagnostic {}
+
 impl FromStr for Instruction {
-    type Err = Box<dyn Error + 'static>;
+    type Err = AsmError;
 
     fn from_str(s: &str) -> Result<Self, Self::Err> {
         use Instruction::*;
-        let mut ps = s.split(" ");
+        let mut ps = s.split_whitespace();
+
+        let instruction = match ps.next().ok_or(AsmError::Empty)? {
+            "addx" => {
+                let operand = ps.next().ok_or(AsmError::MissingOperand("addx"))?;
+                Addx(
+                    operand
+                        .parse()
+                        .or(Err(AsmError::BadOperand(operand.to_string())))?,
+                )
+            }
+            "noop" => Noop,
+            m => return Err(AsmError::UnknownMnemonic(m.to_string())),
+        };
+
+        match ps.next() {
+            Some(extra) => Err(AsmError::ExtraOperand(extra.to_string())),
+            None => Ok(instruction),
+        }
+    }
+}
 
-        match ps.next().ok_or("String is empty!")? {
-            "addx" => Ok(Addx(
-                ps.next().ok_or("Addx instruction too short")?.parse()?,
-            )),
-            "noop" => Ok(Noop),
-            _ => Err(Box::new(MyError::Generic("not a valid instruction"))),
+impl fmt::Display for Instruction {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Instruction::Addx(v) => write!(f, "addx {v}"),
+            Instruction::Noop => write!(f, "noop"),
         }
     }
 }
 
-fn parse(input: &str) -> Result<Vec<Instruction>, Box<dyn Error>> {
-    input.lines().map(|x| Instruction::from_str(x)).collect()
+/// An assembled program. Labels name the position of the instruction after
+/// them; nothing jumps there, but they are kept through the binary encoding.
+#[derive(Debug, Default, PartialEq)]
+struct Program {
+    instructions: Vec<Instruction>,
+    /// Labels with the index of the instruction they name, in order.
+    labels: Vec<(String, usize)>,
+}
+
+const OP_NOOP: u8 = 0;
+const OP_ADDX: u8 = 1;
+const OP_LABEL: u8 = 2;
+
+fn write_varint(out: &mut Vec<u8>, mut n: u64) {
+    while n >= 0x80 {
+        out.push(n as u8 | 0x80);
+        n >>= 7;
+    }
+    out.push(n as u8);
 }
 
-fn part_two(input: &Vec<Instruction>) -> String {
-    use Instruction::*;
-    let mut x = 1;
-    let mut cycle = 0;
-    let mut processing = 0;
+fn read_varint(bytes: &[u8], pos: &mut usize, start: usize) -> Result<u64, AsmError> {
+    let mut n = 0;
+    for shift in (0..64).step_by(7) {
+        let b = *bytes.get(*pos).ok_or(AsmError::Truncated(start))?;
+        *pos += 1;
+        n |= ((b & 0x7f) as u64) << shift;
+        if b & 0x80 == 0 {
+            return Ok(n);
+        }
+    }
+    Err(AsmError::Truncated(start))
+}
 
-    let mut iter = input.iter();
-    let mut pos = Some(&Instruction::Noop);
-    let mut s = String::with_capacity(40 * 7);
+impl Program {
+    /// One byte per `noop`, usually two per `addx`.
+    fn encode(&self) -> Vec<u8> {
+        let mut out = Vec::with_capacity(self.instructions.len() * 2);
+        let mut labels = self.labels.iter().peekable();
 
-    while let Some(i) = pos {
-        if cycle > 0 {
-            if (((cycle - 1) % 40 - x) as isize).abs() <= 1 {
-                s.push('#');
-            } else {
-                s.push('.');
+        for i in 0..=self.instructions.len() {
+            while let Some((label, _)) = labels.next_if(|(_, at)| *at == i) {
+                out.push(OP_LABEL);
+                write_varint(&mut out, label.len() as u64);
+                out.extend(label.bytes());
             }
-            if cycle % 40 == 0 {
-                s.push('\n');
+            match self.instructions.get(i) {
+                Some(Instruction::Noop) => out.push(OP_NOOP),
+                Some(Instruction::Addx(v)) => {
+                    out.push(OP_ADDX);
+                    // zigzag, so small negative numbers stay short
+                    let v = *v as i64;
+                    write_varint(&mut out, ((v << 1) ^ (v >> 63)) as u64);
+                }
+                None => (),
             }
         }
+        out
+    }
 
-        if processing == 0 {
-            if let Addx(s) = i {
-                x += s;
+    fn decode(bytes: &[u8]) -> Result<Self, AsmError> {
+        let mut program = Program::default();
+        let mut pos = 0;
+
+        while let Some(&op) = bytes.get(pos) {
+            let start = pos;
+            pos += 1;
+            match op {
+                OP_NOOP => program.instructions.push(Instruction::Noop),
+                OP_ADDX => {
+                    let n = read_varint(bytes, &mut pos, start)?;
+                    let v = (n >> 1) as i64 ^ -((n & 1) as i64);
+                    program.instructions.push(Instruction::Addx(v as isize));
+                }
+                OP_LABEL => {
+                    let len = read_varint(bytes, &mut pos, start)? as usize;
+                    let label = bytes
+                        .get(pos..pos.saturating_add(len))
+                        .ok_or(AsmError::Truncated(start))?;
+                    let label = String::from_utf8_lossy(label).into_owned();
+                    pos += len;
+                    program.labels.push((label, program.instructions.len()));
+                }
+                op => return Err(AsmError::UnknownOpcode(start, op)),
             }
-            pos = iter.next();
-            if let Some(i) = pos {
-                if let Addx(_) = i {
-                    processing += 2;
-                } else {
-                    processing += 1;
+        }
+        Ok(program)
+    }
+}
+
+impl FromStr for Program {
+    type Err = Diagnostic;
+
+    /// One instruction per line, optionally after a `label:`. Everything after
+    /// a `;` is a comment.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut program = Program::default();
+
+        for (i, line) in s.lines().enumerate() {
+            let at = |error| Diagnostic { line: i + 1, error };
+            let mut code = line.split(';').next().unwrap_or_default().trim();
+
+            if let Some((label, rest)) = code.split_once(':') {
+                let label = label.trim();
+                if label.is_empty() || !label.chars().all(|c| c.is_alphanumeric() || c == '_') {
+                    return Err(at(AsmError::BadLabel(label.to_string())));
+                }
+                if program.labels.iter().any(|(l, _)| l == label) {
+                    return Err(at(AsmError::DuplicateLabel(label.to_string())));
                 }
+                program
+                    .labels
+                    .push((label.to_string(), program.instructions.len()));
+                code = rest.trim();
+            }
+
+            if !code.is_empty() {
+                program.instructions.push(code.parse().map_err(at)?);
+            }
+        }
+        Ok(program)
+    }
+}
+
+impl fmt::Display for Program {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut labels = self.labels.iter().peekable();
+        for i in 0..=self.instructions.len() {
+            while let Some((label, _)) = labels.next_if(|(_, at)| *at == i) {
+                writeln!(f, "{label}:")?;
+            }
+            if let Some(instruction) = self.instructions.get(i) {
+                writeln!(f, "{instruction}")?;
+            }
+        }
+        Ok(())
+    }
+}
+
+fn parse(input: &str) -> Result<Vec<Instruction>, Diagnostic> {
+    Ok(input.parse::<Program>()?.instructions)
+}
+
+/// The registers of the CPU.
+#[derive(Clone, Copy, Debug, PartialEq)]
+struct Registers {
+    x: isize,
+}
+
+impl Default for Registers {
+    fn default() -> Self {
+        Registers { x: 1 }
+    }
+}
+
+impl Registers {
+    fn get(&self, name: &str) -> Option<isize> {
+        match name {
+            "x" | "X" => Some(self.x),
+            _ => None,
+        }
+    }
+}
+
+/// Something the `Cpu` can run. New instructions implement this (possibly
+/// wrapping `Instruction`) and get a cycle cost in the `Cpu`.
+trait Execute {
+    fn mnemonic(&self) -> &str;
+    /// Applies the effect at the end of the last cycle of the instruction.
+    fn execute(&self, registers: &mut Registers);
+}
+
+impl Execute for Instruction {
+    fn mnemonic(&self) -> &str {
+        match self {
+            Instruction::Addx(_) => "addx",
+            Instruction::Noop => "noop",
+        }
+    }
+
+    fn execute(&self, registers: &mut Registers) {
+        if let Instruction::Addx(v) = self {
+            registers.x += v;
+        }
+    }
+}
+
+struct Cpu {
+    /// Cycles every instruction takes, by mnemonic.
+    costs: HashMap<String, usize>,
+    registers: Registers,
+}
+
+impl Default for Cpu {
+    fn default() -> Self {
+        Cpu::new([("addx", 2), ("noop", 1)])
+    }
+}
+
+/// A single clock cycle of the CPU.
+#[derive(Clone, Copy, Debug, PartialEq)]
+struct Tick {
+    /// Starts at 1.
+    cycle: usize,
+    /// The registers during the cycle.
+    during: Registers,
+    /// The registers after the cycle, including the effect of an instruction
+    /// finishing in it.
+    after: Registers,
+    /// The index of the running instruction in the program.
+    instruction: usize,
+    /// Whether the instruction begins executing in this cycle.
+    starts: bool,
+    /// Whether the instruction finishes executing in this cycle.
+    finishes: bool,
+}
+
+impl Cpu {
+    fn new<'a>(costs: impl IntoIterator<Item = (&'a str, usize)>) -> Self {
+        Cpu {
+            costs: costs
+                .into_iter()
+                .map(|(mnemonic, cycles)| (mnemonic.to_string(), cycles))
+                .collect(),
+            registers: Registers::default(),
+        }
+    }
+
+    fn cost<I: Execute>(&self, instruction: &I) -> Result<usize, MyError> {
+        self.costs
+            .get(instruction.mnemonic())
+            .copied()
+            .ok_or(MyError::Generic("no cycle cost for instruction"))
+    }
+
+    /// Runs `program` tick by tick.
+    fn run<'a, I: Execute>(&'a self, program: &'a [I]) -> Result<Ticks<'a, I>, MyError> {
+        let mut costs = Vec::with_capacity(program.len());
+        for instruction in program {
+            costs.push(self.cost(instruction)?);
+        }
+
+        Ok(Ticks {
+            program,
+            costs,
+            next: 0,
+            current: None,
+            registers: self.registers,
+            cycle: 0,
+        })
+    }
+}
+
+struct Ticks<'a, I> {
+    program: &'a [I],
+    costs: Vec<usize>,
+    /// The index of the next instruction to start.
+    next: usize,
+    /// The index of the running instruction and its remaining cycles.
+    current: Option<(usize, usize)>,
+    registers: Registers,
+    cycle: usize,
+}
+
+impl<'a, I: Execute> Iterator for Ticks<'a, I> {
+    type Item = Tick;
+
+    fn next(&mut self) -> Option<Tick> {
+        while !matches!(self.current, Some((_, remaining)) if remaining > 0) {
+            let instruction = self.program.get(self.next)?;
+            let cycles = self.costs[self.next];
+            // instructions without cost take effect right away
+            if cycles == 0 {
+                instruction.execute(&mut self.registers);
+            }
+            self.current = Some((self.next, cycles));
+            self.next += 1;
+        }
+
+        let (i, remaining) = self.current.as_mut()?;
+        let starts = *remaining == self.costs[*i];
+        let during = self.registers;
+        *remaining -= 1;
+        if *remaining == 0 {
+            self.program[*i].execute(&mut self.registers);
+        }
+        self.cycle += 1;
+
+        Some(Tick {
+            cycle: self.cycle,
+            during,
+            after: self.registers,
+            instruction: *i,
+            starts,
+            finishes: *remaining == 0,
+        })
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Comparison {
+    Less,
+    LessEqual,
+    Greater,
+    GreaterEqual,
+    Equal,
+    NotEqual,
+}
+
+impl Comparison {
+    const SYMBOLS: [(&'static str, Comparison); 6] = [
+        ("<=", Comparison::LessEqual),
+        (">=", Comparison::GreaterEqual),
+        ("==", Comparison::Equal),
+        ("!=", Comparison::NotEqual),
+        ("<", Comparison::Less),
+        (">", Comparison::Greater),
+    ];
+
+    fn holds(&self, a: isize, b: isize) -> bool {
+        match self {
+            Comparison::Less => a < b,
+            Comparison::LessEqual => a <= b,
+            Comparison::Greater => a > b,
+            Comparison::GreaterEqual => a >= b,
+            Comparison::Equal => a == b,
+            Comparison::NotEqual => a != b,
+        }
+    }
+}
+
+/// Where the `Debugger` stops, always right before a cycle.
+#[derive(Clone, Debug, PartialEq)]
+enum Breakpoint {
+    Cycle(usize),
+    Condition(String, Comparison, isize),
+}
+
+impl Breakpoint {
+    fn hit(&self, tick: &Tick) -> bool {
+        match self {
+            Breakpoint::Cycle(c) => tick.cycle == *c,
+            Breakpoint::Condition(register, cmp, value) => tick
+                .during
+                .get(register)
+                .is_some_and(|r| cmp.holds(r, *value)),
+        }
+    }
+}
+
+impl fmt::Display for Breakpoint {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Breakpoint::Cycle(c) => write!(f, "cycle {c}"),
+            Breakpoint::Condition(register, cmp, value) => {
+                let (symbol, _) = Comparison::SYMBOLS
+                    .iter()
+                    .find(|(_, c)| c == cmp)
+                    .ok_or(fmt::Error)?;
+                write!(f, "{register} {symbol} {value}")
             }
         }
-        cycle += 1;
-        processing -= 1;
     }
-    s
 }
 
-fn part_one(input: &Vec<Instruction>) -> isize {
-    use Instruction::*;
-    let mut x = 1;
-    let mut strengths = 0;
-    let mut cycle = 0;
-    let mut processing = 0;
+impl FromStr for Breakpoint {
+    type Err = MyError;
 
-    let mut iter = input.iter();
-    let mut pos = Some(&Instruction::Noop);
+    /// Either a cycle number or a condition like `x < 0`.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let s = s.trim();
+        if let Ok(cycle) = s.parse() {
+            return Ok(Breakpoint::Cycle(cycle));
+        }
+
+        let i = s.find(['<', '>', '=', '!']).ok_or(MyError::Generic(
+            "breakpoint is neither a cycle nor a condition",
+        ))?;
+        let register = s[..i].trim();
+        if Registers::default().get(register).is_none() {
+            return Err(MyError::Generic("unknown register in breakpoint"));
+        }
+        let (symbol, cmp) = Comparison::SYMBOLS
+            .iter()
+            .find(|(symbol, _)| s[i..].starts_with(symbol))
+            .ok_or(MyError::Generic("unknown comparison in breakpoint"))?;
+        let value = s[i + symbol.len()..]
+            .trim()
+            .parse()
+            .or(Err(MyError::Generic("can't parse value in breakpoint")))?;
+
+        Ok(Breakpoint::Condition(register.to_string(), *cmp, value))
+    }
+}
+
+enum Command {
+    Break(Breakpoint),
+    Delete(usize),
+    Step(usize),
+    Continue,
+    Print,
+    Crt,
+    Quit,
+}
+
+impl FromStr for Command {
+    type Err = MyError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (command, arg) = s.trim().split_once(' ').unwrap_or((s.trim(), ""));
+        let number = |default| match arg.trim() {
+            "" => Ok(default),
+            n => n.parse().or(Err(MyError::Generic("can't parse number"))),
+        };
+
+        match command {
+            "b" | "break" => Ok(Command::Break(arg.parse()?)),
+            "d" | "delete" => Ok(Command::Delete(number(0)?)),
+            "s" | "step" => Ok(Command::Step(number(1)?)),
+            "c" | "continue" => Ok(Command::Continue),
+            "p" | "print" => Ok(Command::Print),
+            "crt" => Ok(Command::Crt),
+            "q" | "quit" => Ok(Command::Quit),
+            _ => Err(MyError::Generic("unknown command")),
+        }
+    }
+}
 
-    while let Some(i) = pos {
-        if (cycle as isize - 20) % 40 == 0 {
-            strengths += x * cycle as isize;
+/// Runs a program cycle by cycle, driven by commands like `break x < 0`,
+/// `step 3`, `continue`, `print`, `crt` and `quit`, one per line.
+struct Debugger<'a, I: Execute> {
+    program: &'a [I],
+    ticks: Peekable<Ticks<'a, I>>,
+    breakpoints: Vec<Breakpoint>,
+    /// The registers after the last cycle.
+    registers: Registers,
+    cycle: usize,
+    /// Whether a breakpoint already stopped the program before its first
+    /// cycle, so continuing runs it.
+    held: bool,
+    /// The CRT row currently drawn.
+    row: String,
+}
+
+fn sprite(x: isize) -> String {
+    (0..40)
+        .map(|pos: isize| if (pos - x).abs() <= 1 { '#' } else { '.' })
+        .collect()
+}
+
+impl<'a, I: Execute + fmt::Display> Debugger<'a, I> {
+    fn new(cpu: &'a Cpu, program: &'a [I]) -> Result<Self, MyError> {
+        Ok(Debugger {
+            program,
+            ticks: cpu.run(program)?.peekable(),
+            breakpoints: Vec::new(),
+            registers: cpu.registers,
+            cycle: 0,
+            held: false,
+            row: String::with_capacity(40),
+        })
+    }
+
+    /// Executes a single cycle, narrating it like the puzzle when `verbose`.
+    /// Returns false if the program already finished.
+    /// Whether a breakpoint stops the program before the next cycle, which
+    /// is then reported.
+    fn breaks(&mut self, out: &mut impl Write) -> io::Result<bool> {
+        let Some(tick) = self.ticks.peek() else {
+            return Ok(false);
+        };
+        let Some(i) = self.breakpoints.iter().position(|b| b.hit(tick)) else {
+            return Ok(false);
+        };
+        writeln!(
+            out,
+            "Breakpoint {} ({}) hit before cycle {}",
+            i + 1,
+            self.breakpoints[i],
+            tick.cycle
+        )?;
+        Ok(true)
+    }
+
+    fn step(&mut self, out: &mut impl Write, verbose: bool) -> io::Result<bool> {
+        let Some(tick) = self.ticks.next() else {
+            writeln!(out, "Program finished after {} cycles", self.cycle)?;
+            return Ok(false);
+        };
+        let instruction = &self.program[tick.instruction];
+        let pos = (tick.cycle - 1) % 40;
+
+        if pos == 0 {
+            self.row.clear();
         }
+        self.row.push(if (pos as isize - tick.during.x).abs() <= 1 {
+            '#'
+        } else {
+            '.'
+        });
+        self.registers = tick.after;
+        self.cycle = tick.cycle;
 
-        if processing == 0 {
-            if let Addx(s) = i {
-                x += s;
+        if verbose {
+            if tick.starts {
+                writeln!(
+                    out,
+                    "Start cycle {:>3}: begin executing {instruction}",
+                    tick.cycle
+                )?;
+            }
+            writeln!(
+                out,
+                "During cycle {:>2}: CRT draws pixel in position {pos}",
+                tick.cycle
+            )?;
+            writeln!(out, "Current CRT row: {}", self.row)?;
+            if tick.finishes {
+                writeln!(
+                    out,
+                    "End of cycle {:>2}: finish executing {instruction} (Register X is now {})",
+                    tick.cycle, tick.after.x
+                )?;
+                writeln!(out, "Sprite position: {}", sprite(tick.after.x))?;
             }
-            pos = iter.next();
-            if let Some(i) = pos {
-                if let Addx(_) = i {
-                    processing += 2;
-                } else {
-                    processing += 1;
+            writeln!(out)?;
+        }
+        Ok(true)
+    }
+
+    fn run(&mut self, input: impl BufRead, out: &mut impl Write) -> io::Result<()> {
+        for line in input.lines() {
+            let command = match line?.parse() {
+                Ok(command) => command,
+                Err(e) => {
+                    writeln!(out, "{e}")?;
+                    continue;
+                }
+            };
+
+            match command {
+                Command::Break(b) => {
+                    writeln!(out, "Breakpoint {}: {b}", self.breakpoints.len() + 1)?;
+                    self.breakpoints.push(b);
+                }
+                Command::Delete(n) if (1..=self.breakpoints.len()).contains(&n) => {
+                    self.breakpoints.remove(n - 1);
                 }
+                Command::Delete(n) => writeln!(out, "No breakpoint {n}")?,
+                Command::Step(n) => {
+                    for _ in 0..n {
+                        if !self.step(out, true)? {
+                            break;
+                        }
+                    }
+                }
+                Command::Continue => {
+                    // nothing ran yet, so the first cycle may already break
+                    if self.cycle == 0 && !self.held && self.breaks(out)? {
+                        self.held = true;
+                        continue;
+                    }
+                    while self.step(out, false)? {
+                        if self.breaks(out)? {
+                            break;
+                        }
+                    }
+                }
+                Command::Print => writeln!(out, "Cycle {}: X = {}", self.cycle, self.registers.x)?,
+                Command::Crt => {
+                    writeln!(out, "Sprite position: {}", sprite(self.registers.x))?;
+                    writeln!(out, "Current CRT row: {}", self.row)?;
+                }
+                Command::Quit => break,
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Finds a program drawing `screen`, six rows of up to 40 pixels with `#`
+/// for lit ones, on the CRT. There is none if the first pixel is dark, as X
+/// starts at 1.
+fn synthesize(screen: &str) -> Option<Program> {
+    const LOW: isize = -2;
+    const HIGH: isize = 41;
+    const WIDTH: usize = (HIGH - LOW + 1) as usize;
+
+    let rows: Vec<&str> = screen.lines().collect();
+    if rows.len() != 6 || rows.iter().any(|r| r.chars().count() > 40) {
+        return None;
+    }
+    let lit: Vec<bool> = rows
+        .iter()
+        .flat_map(|r| r.chars().chain(std::iter::repeat('.')).take(40))
+        .map(|c| c == '#')
+        .collect();
+    let valid = |i: usize, x: isize| (((i % 40) as isize - x).abs() <= 1) == lit[i];
+    let cycles = lit.len();
+
+    // feasible[i][x]: the rest of the screen can be drawn from cycle i + 1 on
+    // with X = x at its start
+    let mut feasible = vec![[false; WIDTH]; cycles + 1];
+    feasible[cycles] = [true; WIDTH];
+    for i in (0..cycles).rev() {
+        let addx_target = i + 1 < cycles && feasible[i + 2].iter().any(|&f| f);
+        for x in LOW..=HIGH {
+            feasible[i][(x - LOW) as usize] = valid(i, x)
+                && (feasible[i + 1][(x - LOW) as usize] || addx_target && valid(i + 1, x));
+        }
+    }
+
+    let mut x = Registers::default().x;
+    if !feasible[0][(x - LOW) as usize] {
+        return None;
+    }
+    let mut program = Program::default();
+    let mut i = 0;
+    while i < cycles {
+        let target = match i + 1 < cycles && valid(i + 1, x) {
+            true => (LOW..=HIGH)
+                .filter(|&t| feasible[i + 2][(t - LOW) as usize])
+                .min_by_key(|&t| (t - x).abs()),
+            false => None,
+        };
+        match target {
+            Some(t) => {
+                program.instructions.push(Instruction::Addx(t - x));
+                x = t;
+                i += 2;
+            }
+            None => {
+                program.instructions.push(Instruction::Noop);
+                i += 1;
             }
         }
-        cycle += 1;
-        processing -= 1;
     }
+    Some(program)
+}
+
+fn part_two(input: &[Instruction]) -> Result<String, MyError> {
+    let mut s = String::with_capacity(41 * 6);
+
+    for tick in Cpu::default().run(input)? {
+        let pos = ((tick.cycle - 1) % 40) as isize;
+        if (pos - tick.during.x).abs() <= 1 {
+            s.push('#');
+        } else {
+            s.push('.');
+        }
+        if tick.cycle % 40 == 0 {
+            s.push('\n');
+        }
+    }
+    Ok(s)
+}
 
-    strengths
+fn part_one(input: &[Instruction]) -> Result<isize, MyError> {
+    Ok(Cpu::default()
+        .run(input)?
+        .filter(|tick| tick.cycle % 40 == 20)
+        .map(|tick| tick.cycle as isize * tick.during.x)
+        .sum())
 }
 
 fn main() -> Result<(), Box<dyn std::error::Error + 'static>> {
-    let input = parse(&get_input("2022", "10"))?;
+    let args: Vec<String> = env::args().collect();
+
+    if let Some(letters) = args.iter().find_map(|a| a.strip_prefix("--draw=")) {
+        match synthesize(&ocr::render(letters)?) {
+            Some(program) => print!("{program}"),
+            None => println!("{letters} can't be drawn, X starts at 1"),
+        }
+        return Ok(());
+    }
+    if let Some(path) = args.iter().find_map(|a| a.strip_prefix("--disassemble=")) {
+        print!("{}", Program::decode(&fs::read(path)?)?);
+        return Ok(());
+    }
+
+    let source = get_input("2022", "10");
+    if let Some(path) = args.iter().find_map(|a| a.strip_prefix("--encode=")) {
+        fs::write(path, source.parse::<Program>()?.encode())?;
+    }
+    let input = parse(&source)?;
+
+    if args.iter().any(|a| a == "--debug") {
+        let cpu = Cpu::default();
+        Debugger::new(&cpu, &input)?.run(io::stdin().lock(), &mut io::stdout())?;
+        return Ok(());
+    }
 
-    println!("Solution part one: {}", part_one(&input));
-    println!("Solution part two: \n{}", part_two(&input));
+    println!("Solution part one: {}", part_one(&input)?);
+    let screen = part_two(&input)?;
+    match ocr::recognize(&screen) {
+        Ok(letters) => println!("Solution part two: {letters}"),
+        Err(e) => println!("Solution part two: \n{screen}{e}"),
+    }
     Ok(())
 }
 
@@ -292,13 +976,13 @@ noop";
     fn test_part_one() {
         let input = parse(TEST).unwrap();
 
-        assert_eq!(part_one(&input), 13140);
+        assert_eq!(part_one(&input), Ok(13140));
     }
 
     #[test]
     fn test_part_two() {
         let input = parse(TEST).unwrap();
-        let s = part_two(&input);
+        let s = part_two(&input).unwrap();
 
         println!("{}", s);
         assert_eq!(
@@ -312,4 +996,201 @@ noop";
 "
         );
     }
+
+    #[test]
+    fn test_ticks() {
+        let input = parse("noop\naddx 3\naddx -5").unwrap();
+        let xs: Vec<(usize, isize, isize)> = Cpu::default()
+            .run(&input)
+            .unwrap()
+            .map(|t| (t.cycle, t.during.x, t.after.x))
+            .collect();
+
+        assert_eq!(
+            xs,
+            vec![(1, 1, 1), (2, 1, 1), (3, 1, 4), (4, 4, 4), (5, 4, -1)]
+        );
+    }
+
+    #[test]
+    fn test_new_instruction() {
+        enum Extended {
+            Base(Instruction),
+            Mulx(isize),
+        }
+
+        impl Execute for Extended {
+            fn mnemonic(&self) -> &str {
+                match self {
+                    Extended::Base(i) => i.mnemonic(),
+                    Extended::Mulx(_) => "mulx",
+                }
+            }
+
+            fn execute(&self, registers: &mut Registers) {
+                match self {
+                    Extended::Base(i) => i.execute(registers),
+                    Extended::Mulx(v) => registers.x *= v,
+                }
+            }
+        }
+
+        let program = [
+            Extended::Mulx(3),
+            Extended::Base(Instruction::Addx(2)),
+            Extended::Base(Instruction::Noop),
+        ];
+        assert_eq!(
+            Cpu::default().run(&program).err(),
+            Some(MyError::Generic("no cycle cost for instruction"))
+        );
+
+        let cpu = Cpu::new([("addx", 2), ("noop", 1), ("mulx", 3)]);
+        let xs: Vec<isize> = cpu.run(&program).unwrap().map(|t| t.after.x).collect();
+        assert_eq!(xs, vec![1, 1, 3, 3, 5, 5]);
+    }
+
+    #[test]
+    fn test_debugger() {
+        let input = parse(TEST).unwrap();
+        let cpu = Cpu::default();
+        let script = "step 2
+crt
+break 5
+continue
+print
+delete 1
+break x >= 30
+continue
+print
+break y < 0
+delete 1
+continue
+";
+        let mut out = Vec::new();
+        Debugger::new(&cpu, &input)
+            .unwrap()
+            .run(script.as_bytes(), &mut out)
+            .unwrap();
+
+        assert_eq!(
+            String::from_utf8(out).unwrap(),
+            "Start cycle   1: begin executing addx 15
+During cycle  1: CRT draws pixel in position 0
+Current CRT row: #
+
+During cycle  2: CRT draws pixel in position 1
+Current CRT row: ##
+End of cycle  2: finish executing addx 15 (Register X is now 16)
+Sprite position: ...............###......................
+
+Sprite position: ...............###......................
+Current CRT row: ##
+Breakpoint 1: cycle 5
+Breakpoint 1 (cycle 5) hit before cycle 5
+Cycle 4: X = 5
+Breakpoint 1: x >= 30
+Breakpoint 1 (x >= 30) hit before cycle 32
+Cycle 31: X = 33
+unknown register in breakpoint
+Program finished after 240 cycles
+"
+        );
+
+        // stops before the first cycle, then runs on from there
+        let mut out = Vec::new();
+        Debugger::new(&cpu, &input)
+            .unwrap()
+            .run(
+                "break 1\ncontinue\nprint\nbreak 3\ncontinue\nprint\n".as_bytes(),
+                &mut out,
+            )
+            .unwrap();
+        assert_eq!(
+            String::from_utf8(out).unwrap(),
+            "Breakpoint 1: cycle 1
+Breakpoint 1 (cycle 1) hit before cycle 1
+Cycle 0: X = 1
+Breakpoint 2: cycle 3
+Breakpoint 2 (cycle 3) hit before cycle 3
+Cycle 2: X = 16
+"
+        );
+    }
+
+    #[test]
+    fn test_assembler() {
+        let source = "start: addx 3 ; the first one
+
+  noop
+loop:
+end:";
+        let program: Program = source.parse().unwrap();
+        assert_eq!(
+            program.instructions,
+            vec![Instruction::Addx(3), Instruction::Noop]
+        );
+        assert_eq!(
+            program.labels,
+            vec![
+                (String::from("start"), 0),
+                (String::from("loop"), 2),
+                (String::from("end"), 2)
+            ]
+        );
+        assert_eq!(program.to_string(), "start:\naddx 3\nnoop\nloop:\nend:\n");
+
+        let error = |s: &str| s.parse::<Program>().unwrap_err();
+        assert_eq!(
+            error("noop\naddx\n"),
+            Diagnostic {
+                line: 2,
+                error: AsmError::MissingOperand("addx")
+            }
+        );
+        assert_eq!(
+            error("mulx 3").error,
+            AsmError::UnknownMnemonic("mulx".into())
+        );
+        assert_eq!(error("addx x").error, AsmError::BadOperand("x".into()));
+        assert_eq!(error("noop 1").error, AsmError::ExtraOperand("1".into()));
+        assert_eq!(error("a b: noop").error, AsmError::BadLabel("a b".into()));
+        assert_eq!(
+            error("a:\nnoop\na: noop").to_string(),
+            "line 3: label `a` is defined twice"
+        );
+    }
+
+    #[test]
+    fn test_encoding() {
+        let program: Program = format!("begin:\n{TEST}\nend:").parse().unwrap();
+        let bytes = program.encode();
+
+        assert!(bytes.len() < TEST.len() / 3);
+        assert_eq!(Program::decode(&bytes), Ok(program));
+        assert_eq!(
+            Program::decode(&[OP_NOOP, OP_ADDX]),
+            Err(AsmError::Truncated(1))
+        );
+        assert_eq!(Program::decode(&[7]), Err(AsmError::UnknownOpcode(0, 7)));
+
+        let big = Program {
+            instructions: vec![Instruction::Addx(isize::MIN), Instruction::Addx(-1)],
+            labels: vec![],
+        };
+        assert_eq!(Program::decode(&big.encode()), Ok(big));
+    }
+
+    #[test]
+    fn test_synthesize() {
+        let screen = ocr::render("EHZFZHCZ").unwrap();
+        let program = synthesize(&screen).unwrap();
+
+        assert_eq!(part_two(&program.instructions).unwrap(), screen);
+        assert_eq!(
+            ocr::recognize(&part_two(&program.instructions).unwrap()),
+            Ok(String::from("EHZFZHCZ"))
+        );
+        assert!(synthesize(&ocr::render("ABCEFGHJ").unwrap()).is_none());
+    }
 }
@@ -1,98 +1,584 @@
 use lazy_static::lazy_static;
 use regex::Regex;
 use rustvent2022::get_input;
-use std::collections::VecDeque;
+use std::collections::{HashMap, VecDeque};
+use std::env;
 use std::error::Error;
 use std::fmt;
+use std::hash::Hash;
+use std::iter::Peekable;
 use std::str::FromStr;
+use std::sync::Arc;
+use std::thread;
 
-#[derive(Debug)]
+#[derive(Debug, PartialEq)]
 enum MyError {
     Generic(&'static str),
+    Overflow,
+    Unsupported(&'static str),
 }
 
 impl fmt::Display for MyError {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         match self {
             MyError::Generic(s) => write!(f, "{}", s),
+            MyError::Overflow => write!(f, "worry level out of range"),
+            MyError::Unsupported(s) => write!(f, "unsupported worry operation: {}", s),
         }
     }
 }
 
 impl Error for MyError {}
 
-#[derive(Clone)]
+impl From<fmt::Error> for MyError {
+    fn from(_: fmt::Error) -> Self {
+        MyError::Generic("could not write trace")
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
 enum Operation {
     Add,
+    Sub,
     Mult,
 }
 
-#[derive(Clone)]
+impl Operation {
+    fn precedence(&self) -> u8 {
+        match self {
+            Operation::Add | Operation::Sub => 1,
+            Operation::Mult => 2,
+        }
+    }
+
+    fn apply<W: Worry>(&self, t1: &W, t2: &W) -> Result<W, MyError> {
+        match self {
+            Operation::Add => t1.add(t2),
+            Operation::Sub => t1.sub(t2),
+            Operation::Mult => t1.mul(t2),
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
 enum Term {
     Old,
-    Num(isize),
+    Num(i64),
+}
+
+/// The right hand side of a monkey's `Operation: new = ...` line.
+#[derive(Clone, Debug, PartialEq)]
+enum Expr {
+    Term(Term),
+    BinOp(Operation, Box<Expr>, Box<Expr>),
+}
+
+#[derive(Debug, PartialEq)]
+enum Token {
+    Term(Term),
+    Op(Operation),
+    Open,
+    Close,
+}
+
+impl fmt::Display for Expr {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Expr::Term(Term::Old) => write!(f, "old"),
+            Expr::Term(Term::Num(n)) => write!(f, "{n}"),
+            Expr::BinOp(op, t1, t2) => {
+                let symbol = match op {
+                    Operation::Add => '+',
+                    Operation::Sub => '-',
+                    Operation::Mult => '*',
+                };
+                // operators are left associative, so the right operand
+                // already needs parentheses on equal precedence
+                match t1.as_ref() {
+                    Expr::BinOp(o, _, _) if o.precedence() < op.precedence() => {
+                        write!(f, "({t1})")?
+                    }
+                    _ => write!(f, "{t1}")?,
+                }
+                write!(f, " {symbol} ")?;
+                match t2.as_ref() {
+                    Expr::BinOp(o, _, _) if o.precedence() <= op.precedence() => {
+                        write!(f, "({t2})")
+                    }
+                    _ => write!(f, "{t2}"),
+                }
+            }
+        }
+    }
+}
+
+impl Expr {
+    /// The line of the trace describing how the worry level changed to `new`.
+    fn narrate<W: Worry>(&self, new: &W) -> String {
+        let Expr::BinOp(op, t1, t2) = self else {
+            return format!("Worry level is set to {self} = {new}.");
+        };
+        match (op, t1.as_ref(), t2.as_ref()) {
+            (Operation::Mult, Expr::Term(Term::Old), Expr::Term(Term::Old)) => {
+                format!("Worry level is multiplied by itself to {new}.")
+            }
+            (Operation::Mult, Expr::Term(Term::Old), Expr::Term(Term::Num(n))) => {
+                format!("Worry level is multiplied by {n} to {new}.")
+            }
+            (Operation::Add, Expr::Term(Term::Old), Expr::Term(Term::Num(n))) => {
+                format!("Worry level increases by {n} to {new}.")
+            }
+            (Operation::Sub, Expr::Term(Term::Old), Expr::Term(Term::Num(n))) => {
+                format!("Worry level decreases by {n} to {new}.")
+            }
+            _ => format!("Worry level is set to {self} = {new}."),
+        }
+    }
+
+    fn eval<W: Worry>(&self, old: &W) -> Result<W, MyError> {
+        match self {
+            Expr::Term(Term::Old) => Ok(old.clone()),
+            Expr::Term(Term::Num(n)) => old.constant(*n),
+            Expr::BinOp(op, t1, t2) => op.apply(&t1.eval(old)?, &t2.eval(old)?),
+        }
+    }
+
+    fn tokenize(s: &str) -> Result<Vec<Token>, MyError> {
+        let mut tokens = Vec::new();
+        let mut chars = s.chars().peekable();
+
+        while let Some(&c) = chars.peek() {
+            match c {
+                ' ' => {
+                    chars.next();
+                }
+                '+' | '-' | '*' => {
+                    chars.next();
+                    tokens.push(Token::Op(match c {
+                        '+' => Operation::Add,
+                        '-' => Operation::Sub,
+                        _ => Operation::Mult,
+                    }));
+                }
+                '(' => {
+                    chars.next();
+                    tokens.push(Token::Open);
+                }
+                ')' => {
+                    chars.next();
+                    tokens.push(Token::Close);
+                }
+                '0'..='9' => {
+                    let mut n: i64 = 0;
+                    while let Some(d) = chars.peek().and_then(|c| c.to_digit(10)) {
+                        n = n
+                            .checked_mul(10)
+                            .and_then(|n| n.checked_add(d as i64))
+                            .ok_or(MyError::Generic("number in operation too large"))?;
+                        chars.next();
+                    }
+                    tokens.push(Token::Term(Term::Num(n)));
+                }
+                'o' => {
+                    if chars.by_ref().take(3).collect::<String>() != "old" {
+                        return Err(MyError::Generic("unknown identifier in operation"));
+                    }
+                    tokens.push(Token::Term(Term::Old));
+                }
+                _ => return Err(MyError::Generic("unexpected character in operation")),
+            }
+        }
+
+        Ok(tokens)
+    }
+
+    /// Precedence climbing, all operators are left associative.
+    fn parse_binop<I>(tokens: &mut Peekable<I>, min_precedence: u8) -> Result<Expr, MyError>
+    where
+        I: Iterator<Item = Token>,
+    {
+        let mut lhs = Expr::parse_atom(tokens)?;
+
+        while let Some(Token::Op(op)) = tokens.peek() {
+            let precedence = op.precedence();
+            if precedence < min_precedence {
+                break;
+            }
+            let Some(Token::Op(op)) = tokens.next() else {
+                unreachable!()
+            };
+            let rhs = Expr::parse_binop(tokens, precedence + 1)?;
+            lhs = Expr::BinOp(op, Box::new(lhs), Box::new(rhs));
+        }
+
+        Ok(lhs)
+    }
+
+    fn parse_atom<I>(tokens: &mut Peekable<I>) -> Result<Expr, MyError>
+    where
+        I: Iterator<Item = Token>,
+    {
+        match tokens.next() {
+            Some(Token::Term(t)) => Ok(Expr::Term(t)),
+            Some(Token::Open) => {
+                let inner = Expr::parse_binop(tokens, 0)?;
+                match tokens.next() {
+                    Some(Token::Close) => Ok(inner),
+                    _ => Err(MyError::Generic("missing closing parenthesis")),
+                }
+            }
+            Some(_) => Err(MyError::Generic("expected 'old', a number or '('")),
+            None => Err(MyError::Generic("operation ended unexpectedly")),
+        }
+    }
+}
+
+impl FromStr for Expr {
+    type Err = MyError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut tokens = Expr::tokenize(s)?.into_iter().peekable();
+        let expr = Expr::parse_binop(&mut tokens, 0)?;
+
+        match tokens.next() {
+            None => Ok(expr),
+            Some(Token::Close) => Err(MyError::Generic("unmatched closing parenthesis")),
+            Some(_) => Err(MyError::Generic("unexpected token after operation")),
+        }
+    }
+}
+
+/// A worry level. Arithmetic reports leaving the representable range as
+/// `MyError::Overflow` instead of wrapping around.
+trait Worry: Clone + fmt::Display {
+    /// `divisors` are the divisibility tests of all monkeys of the troop.
+    fn new(n: i64, divisors: &Arc<[i64]>) -> Result<Self, MyError>;
+    /// The number `n` in the same representation as `self`.
+    fn constant(&self, n: i64) -> Result<Self, MyError>;
+    fn add(&self, other: &Self) -> Result<Self, MyError>;
+    fn sub(&self, other: &Self) -> Result<Self, MyError>;
+    fn mul(&self, other: &Self) -> Result<Self, MyError>;
+    fn div(&self, k: i64) -> Result<Self, MyError>;
+    fn rem(&self, m: i64) -> Result<Self, MyError>;
+    fn is_divisible_by(&self, d: i64) -> Result<bool, MyError>;
+}
+
+impl Worry for i64 {
+    fn new(n: i64, _divisors: &Arc<[i64]>) -> Result<Self, MyError> {
+        Ok(n)
+    }
+
+    fn constant(&self, n: i64) -> Result<Self, MyError> {
+        Ok(n)
+    }
+
+    fn add(&self, other: &Self) -> Result<Self, MyError> {
+        self.checked_add(*other).ok_or(MyError::Overflow)
+    }
+
+    fn sub(&self, other: &Self) -> Result<Self, MyError> {
+        self.checked_sub(*other).ok_or(MyError::Overflow)
+    }
+
+    fn mul(&self, other: &Self) -> Result<Self, MyError> {
+        self.checked_mul(*other).ok_or(MyError::Overflow)
+    }
+
+    fn div(&self, k: i64) -> Result<Self, MyError> {
+        self.checked_div_euclid(k).ok_or(MyError::Overflow)
+    }
+
+    fn rem(&self, m: i64) -> Result<Self, MyError> {
+        self.checked_rem_euclid(m).ok_or(MyError::Overflow)
+    }
+
+    fn is_divisible_by(&self, d: i64) -> Result<bool, MyError> {
+        Ok(self.checked_rem(d).ok_or(MyError::Overflow)? == 0)
+    }
+}
+
+impl Worry for u128 {
+    fn new(n: i64, _divisors: &Arc<[i64]>) -> Result<Self, MyError> {
+        u128::try_from(n).or(Err(MyError::Overflow))
+    }
+
+    fn constant(&self, n: i64) -> Result<Self, MyError> {
+        u128::try_from(n).or(Err(MyError::Overflow))
+    }
+
+    fn add(&self, other: &Self) -> Result<Self, MyError> {
+        self.checked_add(*other).ok_or(MyError::Overflow)
+    }
+
+    fn sub(&self, other: &Self) -> Result<Self, MyError> {
+        self.checked_sub(*other).ok_or(MyError::Overflow)
+    }
+
+    fn mul(&self, other: &Self) -> Result<Self, MyError> {
+        self.checked_mul(*other).ok_or(MyError::Overflow)
+    }
+
+    fn div(&self, k: i64) -> Result<Self, MyError> {
+        self.checked_div(u128::try_from(k).or(Err(MyError::Overflow))?)
+            .ok_or(MyError::Overflow)
+    }
+
+    fn rem(&self, m: i64) -> Result<Self, MyError> {
+        self.checked_rem(u128::try_from(m).or(Err(MyError::Overflow))?)
+            .ok_or(MyError::Overflow)
+    }
+
+    fn is_divisible_by(&self, d: i64) -> Result<bool, MyError> {
+        Ok(self.rem(d)? == 0)
+    }
+}
+
+/// A worry level kept only as its residues modulo every divisor of the troop.
+/// It can't overflow, but also can't be divided.
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+struct Residues {
+    moduli: Arc<[i64]>,
+    residues: Vec<i64>,
+}
+
+impl Residues {
+    fn zip_with(&self, other: &Self, f: fn(i128, i128) -> i128) -> Result<Self, MyError> {
+        if self.moduli != other.moduli {
+            return Err(MyError::Unsupported("residues with different moduli"));
+        }
+        let residues = self
+            .moduli
+            .iter()
+            .zip(self.residues.iter().zip(&other.residues))
+            .map(|(&m, (&a, &b))| f(a as i128, b as i128).rem_euclid(m as i128) as i64)
+            .collect();
+        Ok(Residues {
+            moduli: Arc::clone(&self.moduli),
+            residues,
+        })
+    }
+}
+
+impl fmt::Display for Residues {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let parts: Vec<String> = self
+            .moduli
+            .iter()
+            .zip(&self.residues)
+            .map(|(m, r)| format!("{r} mod {m}"))
+            .collect();
+        write!(f, "[{}]", parts.join(", "))
+    }
+}
+
+impl Worry for Residues {
+    fn new(n: i64, divisors: &Arc<[i64]>) -> Result<Self, MyError> {
+        if divisors.iter().any(|&d| d <= 0) {
+            return Err(MyError::Unsupported("non-positive modulus"));
+        }
+        Ok(Residues {
+            moduli: Arc::clone(divisors),
+            residues: divisors.iter().map(|&d| n.rem_euclid(d)).collect(),
+        })
+    }
+
+    fn constant(&self, n: i64) -> Result<Self, MyError> {
+        Residues::new(n, &self.moduli)
+    }
+
+    fn add(&self, other: &Self) -> Result<Self, MyError> {
+        self.zip_with(other, |a, b| a + b)
+    }
+
+    fn sub(&self, other: &Self) -> Result<Self, MyError> {
+        self.zip_with(other, |a, b| a - b)
+    }
+
+    fn mul(&self, other: &Self) -> Result<Self, MyError> {
+        self.zip_with(other, |a, b| a * b)
+    }
+
+    fn div(&self, _k: i64) -> Result<Self, MyError> {
+        Err(MyError::Unsupported("division of residues"))
+    }
+
+    fn rem(&self, m: i64) -> Result<Self, MyError> {
+        // reducing modulo a common multiple of all moduli changes no residue
+        match self.moduli.iter().all(|&d| m % d == 0) {
+            true => Ok(self.clone()),
+            false => Err(MyError::Unsupported("modulus is no multiple of the moduli")),
+        }
+    }
+
+    fn is_divisible_by(&self, d: i64) -> Result<bool, MyError> {
+        let i = self
+            .moduli
+            .iter()
+            .position(|&m| m == d)
+            .ok_or(MyError::Unsupported("divisor is not one of the moduli"))?;
+        Ok(self.residues[i] == 0)
+    }
+}
+
+/// What happens to a worry level after the monkey inspected the item and
+/// before it is tested.
+trait Relief<W> {
+    fn relieve(&self, wl: W) -> Result<W, MyError>;
+
+    /// The line of the trace describing the relief, if there is any.
+    fn narrate(&self, relieved: &W) -> Option<String>
+    where
+        W: fmt::Display,
+    {
+        Some(format!("Worry level is relieved to {relieved}."))
+    }
+}
+
+/// Part one: the worry level is divided by `k`, rounding down.
+struct DivideBy(i64);
+
+/// Part two: no relief, but the worry level can be reduced modulo the least
+/// common multiple of all tests without changing any test result.
+struct ModuloLcm(i64);
+
+/// No relief at all, only sensible with `Residues`.
+struct NoRelief;
+
+impl<W: Worry> Relief<W> for DivideBy {
+    fn relieve(&self, wl: W) -> Result<W, MyError> {
+        wl.div(self.0)
+    }
+
+    fn narrate(&self, relieved: &W) -> Option<String> {
+        Some(format!(
+            "Monkey gets bored with item. Worry level is divided by {} to {relieved}.",
+            self.0
+        ))
+    }
+}
+
+impl<W: Worry> Relief<W> for ModuloLcm {
+    fn relieve(&self, wl: W) -> Result<W, MyError> {
+        wl.rem(self.0)
+    }
+
+    fn narrate(&self, relieved: &W) -> Option<String> {
+        Some(format!(
+            "Worry level is reduced modulo {} to {relieved}.",
+            self.0
+        ))
+    }
+}
+
+impl<W> Relief<W> for NoRelief {
+    fn relieve(&self, wl: W) -> Result<W, MyError> {
+        Ok(wl)
+    }
+
+    fn narrate(&self, _relieved: &W) -> Option<String> {
+        None
+    }
+}
+
+impl<W, F: Fn(W) -> Result<W, MyError>> Relief<W> for F {
+    fn relieve(&self, wl: W) -> Result<W, MyError> {
+        self(wl)
+    }
+}
+
+fn gcd(mut a: i64, mut b: i64) -> i64 {
+    while b != 0 {
+        (a, b) = (b, a % b);
+    }
+    a.abs()
+}
+
+impl ModuloLcm {
+    fn of<W>(monkeys: &[Monkey<W>]) -> Result<Self, MyError> {
+        let lcm = monkeys.iter().try_fold(1, |acc: i64, m| {
+            (acc / gcd(acc, m.test))
+                .checked_mul(m.test)
+                .ok_or(MyError::Overflow)
+        })?;
+        Ok(ModuloLcm(lcm))
+    }
 }
 
 #[derive(Clone)]
-struct Monkey {
-    items: VecDeque<isize>,
-    test: isize,
+struct Monkey<W = i64> {
+    items: VecDeque<W>,
+    test: i64,
     to_true: usize,
     to_false: usize,
-    op: Operation,
-    t1: Term,
-    t2: Term,
+    op: Expr,
     inspected: usize,
 }
 
-impl Monkey {
-    fn inspect_part_two(&mut self, scm: isize) -> Option<(isize, usize)> {
+/// Everything that happens while a monkey inspects a single item.
+#[derive(Debug, PartialEq)]
+struct Inspection<W> {
+    old: W,
+    new: W,
+    relieved: W,
+    divisible: bool,
+    to: usize,
+}
+
+impl<W: Worry> Monkey<W> {
+    /// Inspects an item without taking it from the monkey.
+    fn handle<R: Relief<W>>(&self, old: W, relief: &R) -> Result<Inspection<W>, MyError> {
+        let new = self.op.eval(&old)?;
+        let relieved = relief.relieve(new.clone())?;
+        let divisible = relieved.is_divisible_by(self.test)?;
+
+        Ok(Inspection {
+            old,
+            new,
+            relieved,
+            divisible,
+            to: match divisible {
+                true => self.to_true,
+                false => self.to_false,
+            },
+        })
+    }
+
+    fn inspect<R: Relief<W>>(&mut self, relief: &R) -> Result<Option<Inspection<W>>, MyError> {
         if let Some(wl) = self.items.pop_front() {
             self.inspected += 1;
 
-            let t1 = match self.t1 {
-                Term::Old => wl,
-                Term::Num(n) => n,
-            };
-            let t2 = match self.t2 {
-                Term::Old => wl,
-                Term::Num(n) => n,
-            };
-            let new_wl = match self.op {
-                Operation::Mult => t1 * t2,
-                Operation::Add => t1 + t2,
-            } % scm;
-            Some(match new_wl % self.test == 0 {
-                true => (new_wl, self.to_true),
-                false => (new_wl, self.to_false),
-            })
+            Ok(Some(self.handle(wl, relief)?))
         } else {
-            None
+            Ok(None)
         }
     }
-    fn inspect(&mut self) -> Option<(isize, usize)> {
-        if let Some(wl) = self.items.pop_front() {
-            self.inspected += 1;
 
-            let t1 = match self.t1 {
-                Term::Old => wl,
-                Term::Num(n) => n,
-            };
-            let t2 = match self.t2 {
-                Term::Old => wl,
-                Term::Num(n) => n,
-            };
-            let new_wl = match self.op {
-                Operation::Mult => t1 * t2,
-                Operation::Add => t1 + t2,
-            } / 3;
-            Some(match new_wl % self.test == 0 {
-                true => (new_wl, self.to_true),
-                false => (new_wl, self.to_false),
-            })
-        } else {
-            None
+    fn narrate<R: Relief<W>>(
+        &self,
+        out: &mut dyn fmt::Write,
+        inspection: &Inspection<W>,
+        relief: &R,
+    ) -> fmt::Result {
+        writeln!(
+            out,
+            "  Monkey inspects an item with a worry level of {}.",
+            inspection.old
+        )?;
+        writeln!(out, "    {}", self.op.narrate(&inspection.new))?;
+        if let Some(line) = relief.narrate(&inspection.relieved) {
+            writeln!(out, "    {line}")?;
         }
+        writeln!(
+            out,
+            "    Current worry level is {}divisible by {}.",
+            if inspection.divisible { "" } else { "not " },
+            self.test
+        )?;
+        writeln!(
+            out,
+            "    Item with worry level {} is thrown to monkey {}.",
+            inspection.relieved, inspection.to
+        )
     }
 }
 
@@ -101,7 +587,7 @@ impl FromStr for Monkey {
 
     fn from_str(s: &str) -> Result<Self, Self::Err> {
         lazy_static! {static ref RE: Regex = Regex::new(
-            r"Monkey (?P<monkey_num>\d+):\n  Starting items: (?P<items>(?:\d+, )*\d*)\n  Operation: new = (?P<vfirst>old|\d+) (?P<op>\*|\+) (?P<vsecond>old|\d+)\n  Test: divisible by (?P<divby>\d+)\n    If true: throw to monkey (?P<truemonkey>\d+)\n    If false: throw to monkey (?P<falsemonkey>\d+)",
+            r"Monkey (?P<monkey_num>\d+):\n  Starting items: (?P<items>(?:\d+, )*\d*)\n  Operation: new = (?P<op>[^\n]+)\n  Test: divisible by (?P<divby>\d+)\n    If true: throw to monkey (?P<truemonkey>\d+)\n    If false: throw to monkey (?P<falsemonkey>\d+)",
         ).unwrap();}
 
         let cap = RE.captures(s).ok_or("No captures found")?;
@@ -114,22 +600,9 @@ impl FromStr for Monkey {
         {
             items.push_back(i.parse()?);
         }
-        let t1 = match cap.name("vfirst").ok_or("no cap for vfirst")?.as_str() {
-            "old" => Term::Old,
-            s => Term::Num(s.parse()?),
-        };
-        let t2 = match cap.name("vsecond").ok_or("no cap for vfirst")?.as_str() {
-            "old" => Term::Old,
-            s => Term::Num(s.parse()?),
-        };
+        let op: Expr = cap.name("op").ok_or("no cap for op")?.as_str().parse()?;
 
-        let op = match cap.name("op").ok_or("no cap for op")?.as_str() {
-            "*" => Operation::Mult,
-            "+" => Operation::Add,
-            _ => return Err(Box::new(MyError::Generic("not a known operation"))),
-        };
-
-        let test: isize = cap
+        let test: i64 = cap
             .name("divby")
             .ok_or("no cap for divby")?
             .as_str()
@@ -148,11 +621,9 @@ impl FromStr for Monkey {
             .parse()?;
 
         Ok(Monkey {
-            items: items,
-            test: test,
-            t1: t1,
-            t2: t2,
-            op: op,
+            items,
+            test,
+            op,
             to_true: tm,
             to_false: fm,
             inspected: 0,
@@ -161,50 +632,362 @@ impl FromStr for Monkey {
 }
 
 fn parse(input: &str) -> Result<Vec<Monkey>, Box<dyn Error>> {
-    input.split("\n\n").map(|x| Monkey::from_str(x)).collect()
+    input.split("\n\n").map(Monkey::from_str).collect()
+}
+
+/// Converts the parsed starting items into the worry level type `W`.
+fn with_worry<W: Worry>(input: &[Monkey]) -> Result<Vec<Monkey<W>>, MyError> {
+    let divisors: Arc<[i64]> = input.iter().map(|m| m.test).collect();
+
+    input
+        .iter()
+        .map(|m| {
+            Ok(Monkey {
+                items: m
+                    .items
+                    .iter()
+                    .map(|&n| W::new(n, &divisors))
+                    .collect::<Result<_, _>>()?,
+                test: m.test,
+                to_true: m.to_true,
+                to_false: m.to_false,
+                op: m.op.clone(),
+                inspected: m.inspected,
+            })
+        })
+        .collect()
+}
+
+/// Plays `rounds` rounds of keep away.
+fn simulate<W: Worry, R: Relief<W>>(
+    monkeys: &mut [Monkey<W>],
+    rounds: usize,
+    relief: &R,
+) -> Result<(), MyError> {
+    simulate_traced(monkeys, rounds, relief, None)
 }
 
-fn part_one(mut input: Vec<Monkey>, rounds: usize) -> usize {
-    for _ in 0..rounds {
-        for i in 0..input.len() {
-            while let Some((wl, new_monkey)) = input[i].inspect() {
-                input[new_monkey].items.push_back(wl);
+/// Like `simulate`, but narrates every inspection and the items held after
+/// every round to `trace`, in the words of the puzzle.
+fn simulate_traced<W: Worry, R: Relief<W>>(
+    monkeys: &mut [Monkey<W>],
+    rounds: usize,
+    relief: &R,
+    mut trace: Option<&mut dyn fmt::Write>,
+) -> Result<(), MyError> {
+    for round in 1..=rounds {
+        for i in 0..monkeys.len() {
+            if let Some(out) = trace.as_deref_mut() {
+                writeln!(out, "Monkey {i}:")?;
+            }
+            while let Some(inspection) = monkeys[i].inspect(relief)? {
+                if let Some(out) = trace.as_deref_mut() {
+                    monkeys[i].narrate(out, &inspection, relief)?;
+                }
+                monkeys
+                    .get_mut(inspection.to)
+                    .ok_or(MyError::Generic("thrown to a non-existing monkey"))?
+                    .items
+                    .push_back(inspection.relieved);
             }
         }
+
+        if let Some(out) = trace.as_deref_mut() {
+            writeln!(out)?;
+            writeln!(
+                out,
+                "After round {round}, the monkeys are holding items with these worry levels:"
+            )?;
+            for (i, m) in monkeys.iter().enumerate() {
+                let items: Vec<String> = m.items.iter().map(|wl| wl.to_string()).collect();
+                writeln!(out, "Monkey {i}: {}", items.join(", "))?;
+            }
+            writeln!(out)?;
+        }
     }
+    Ok(())
+}
 
-    let mut business: Vec<usize> = input.iter().map(|m| m.inspected).collect();
+fn monkey_business(inspected: impl IntoIterator<Item = usize>) -> u128 {
+    let mut business: Vec<u128> = inspected.into_iter().map(|n| n as u128).collect();
     business.sort();
 
-    business[business.len() - 2] * business[business.len() - 1]
+    business.iter().rev().take(2).product()
 }
 
-fn part_two(mut input: Vec<Monkey>, rounds: usize) -> usize {
-    // first find a small common multiple (not nessecarily the smallest, but still)
-    let scm = input
-        .iter()
-        .map(|m| m.test)
-        .fold(1, |acc, v| if acc % v != 0 { acc * v } else { acc });
+/// Follows a single item held by monkey `holder` through one round. Returns
+/// its holder and worry level at the end of the round and the monkeys which
+/// inspected it, in order.
+fn item_round<W: Worry, R: Relief<W>>(
+    monkeys: &[Monkey<W>],
+    mut holder: usize,
+    mut wl: W,
+    relief: &R,
+) -> Result<(usize, W, Vec<usize>), MyError> {
+    let mut route = Vec::new();
+
+    loop {
+        let monkey = monkeys
+            .get(holder)
+            .ok_or(MyError::Generic("thrown to a non-existing monkey"))?;
+        route.push(holder);
+        let inspection = monkey.handle(wl, relief)?;
+        wl = inspection.relieved;
 
-    for _ in 0..rounds {
-        for i in 0..input.len() {
-            while let Some((wl, new_monkey)) = input[i].inspect_part_two(scm) {
-                input[new_monkey].items.push_back(wl);
+        // monkeys which did not have their turn yet inspect it again this round
+        if inspection.to < holder {
+            return Ok((inspection.to, wl, route));
+        }
+        holder = inspection.to;
+    }
+}
+
+fn count_inspections(route: &[usize], n_monkeys: usize) -> Vec<usize> {
+    let mut inspected = vec![0; n_monkeys];
+    for &m in route {
+        inspected[m] += 1;
+    }
+    inspected
+}
+
+/// The inspections of a single item, round by round, until its state at the
+/// start of a round (holder and worry level) repeats. Items never influence
+/// each other, so from there on the item loops forever.
+struct ItemCycle {
+    /// Inspections per monkey in every round before the state repeats.
+    rounds: Vec<Vec<usize>>,
+    /// The first round of the loop.
+    start: usize,
+}
+
+impl ItemCycle {
+    fn find<W, R>(monkeys: &[Monkey<W>], holder: usize, wl: W, relief: &R) -> Result<Self, MyError>
+    where
+        W: Worry + Hash + Eq,
+        R: Relief<W>,
+    {
+        let mut seen = HashMap::new();
+        let mut rounds = Vec::new();
+        let mut state = (holder, wl);
+
+        while !seen.contains_key(&state) {
+            let (holder, wl) = state.clone();
+            seen.insert(state, rounds.len());
+            let (holder, wl, route) = item_round(monkeys, holder, wl, relief)?;
+            rounds.push(count_inspections(&route, monkeys.len()));
+            state = (holder, wl);
+        }
+
+        Ok(ItemCycle {
+            start: seen[&state],
+            rounds,
+        })
+    }
+
+    /// Inspections per monkey of the item during the first `n` rounds.
+    fn inspections(&self, n: usize) -> Vec<usize> {
+        let mut inspected = vec![0; self.rounds[0].len()];
+        let mut add = |rounds: &[Vec<usize>], times: usize| {
+            for round in rounds {
+                for (total, k) in inspected.iter_mut().zip(round) {
+                    *total += k * times;
+                }
             }
+        };
+
+        add(&self.rounds[..n.min(self.start)], 1);
+        if n > self.start {
+            let period = self.rounds.len() - self.start;
+            let (full, rest) = ((n - self.start) / period, (n - self.start) % period);
+            add(&self.rounds[self.start..], full);
+            add(&self.rounds[self.start..self.start + rest], 1);
         }
+        inspected
     }
+}
 
-    let mut business: Vec<usize> = input.iter().map(|m| m.inspected).collect();
-    business.sort();
+/// Inspections per monkey after `rounds` more rounds, extrapolated from the
+/// loop every item ends up in instead of simulating all rounds.
+fn extrapolate<W, R>(
+    monkeys: &[Monkey<W>],
+    rounds: usize,
+    relief: &R,
+) -> Result<Vec<usize>, MyError>
+where
+    W: Worry + Hash + Eq,
+    R: Relief<W>,
+{
+    let mut inspected: Vec<usize> = monkeys.iter().map(|m| m.inspected).collect();
+
+    for (holder, monkey) in monkeys.iter().enumerate() {
+        for wl in &monkey.items {
+            let cycle = ItemCycle::find(monkeys, holder, wl.clone(), relief)?;
+            for (total, n) in inspected.iter_mut().zip(cycle.inspections(rounds)) {
+                *total += n;
+            }
+        }
+    }
+    Ok(inspected)
+}
 
-    business[business.len() - 2] * business[business.len() - 1]
+/// The way of a single item through the monkeys.
+#[derive(Debug, PartialEq)]
+struct ItemPath {
+    /// The monkey holding the item at the start and its place in the queue.
+    origin: (usize, usize),
+    /// The monkeys inspecting the item, in order. Every inspection ends with
+    /// a throw, so this is also every throw.
+    route: Vec<usize>,
+    /// Inspections per monkey.
+    inspected: Vec<usize>,
+}
+
+impl ItemPath {
+    fn follow<W: Worry, R: Relief<W>>(
+        monkeys: &[Monkey<W>],
+        origin: (usize, usize),
+        mut wl: W,
+        rounds: usize,
+        relief: &R,
+    ) -> Result<Self, MyError> {
+        let mut holder = origin.0;
+        let mut route = Vec::new();
+
+        for _ in 0..rounds {
+            let (next, new_wl, round) = item_round(monkeys, holder, wl, relief)?;
+            route.extend(round);
+            (holder, wl) = (next, new_wl);
+        }
+
+        Ok(ItemPath {
+            origin,
+            inspected: count_inspections(&route, monkeys.len()),
+            route,
+        })
+    }
+
+    fn throws(&self) -> usize {
+        self.route.len()
+    }
+}
+
+/// Follows every item the monkeys hold for `rounds` rounds, spread over
+/// `threads` threads. The paths are in the order of the items.
+fn analyse<W, R>(
+    monkeys: &[Monkey<W>],
+    rounds: usize,
+    relief: &R,
+    threads: usize,
+) -> Result<Vec<ItemPath>, MyError>
+where
+    W: Worry + Send + Sync,
+    R: Relief<W> + Sync,
+{
+    let items: Vec<((usize, usize), &W)> = monkeys
+        .iter()
+        .enumerate()
+        .flat_map(|(m, monkey)| {
+            monkey
+                .items
+                .iter()
+                .enumerate()
+                .map(move |(i, wl)| ((m, i), wl))
+        })
+        .collect();
+    let chunk_size = items.len().div_ceil(threads.max(1)).max(1);
+
+    thread::scope(|scope| {
+        let handles: Vec<_> = items
+            .chunks(chunk_size)
+            .map(|chunk| {
+                scope.spawn(move || {
+                    chunk
+                        .iter()
+                        .map(|&(origin, wl)| {
+                            ItemPath::follow(monkeys, origin, wl.clone(), rounds, relief)
+                        })
+                        .collect::<Result<Vec<_>, _>>()
+                })
+            })
+            .collect();
+
+        let mut paths = Vec::with_capacity(items.len());
+        for handle in handles {
+            paths.extend(handle.join().expect("analysis thread panicked")?);
+        }
+        Ok(paths)
+    })
+}
+
+/// Inspections per monkey of all items together.
+fn total_inspections(paths: &[ItemPath], n_monkeys: usize) -> Vec<usize> {
+    let mut inspected = vec![0; n_monkeys];
+    for path in paths {
+        for (total, n) in inspected.iter_mut().zip(&path.inspected) {
+            *total += n;
+        }
+    }
+    inspected
+}
+
+fn part_one<W: Worry>(mut input: Vec<Monkey<W>>, rounds: usize) -> Result<u128, MyError> {
+    simulate(&mut input, rounds, &DivideBy(3))?;
+    Ok(monkey_business(input.iter().map(|m| m.inspected)))
+}
+
+fn part_two<W: Worry>(mut input: Vec<Monkey<W>>, rounds: usize) -> Result<u128, MyError> {
+    let relief = ModuloLcm::of(&input)?;
+    simulate(&mut input, rounds, &relief)?;
+    Ok(monkey_business(input.iter().map(|m| m.inspected)))
 }
 
 fn main() -> Result<(), Box<dyn std::error::Error + 'static>> {
     let input = parse(&get_input("2022", "11"))?;
+    let args: Vec<String> = env::args().collect();
+
+    if args.iter().any(|a| a == "--trace") {
+        let mut trace = String::new();
+        simulate_traced(&mut input.clone(), 1, &DivideBy(3), Some(&mut trace))?;
+        print!("{trace}");
+    }
 
-    println!("Solution part one: {}", part_one(input.clone(), 20));
-    println!("Solution part two: {}", part_two(input.clone(), 10000));
+    println!("Solution part one: {}", part_one(input.clone(), 20)?);
+    println!(
+        "Solution part two: {}",
+        part_two(with_worry::<Residues>(&input)?, 10000)?
+    );
+
+    if args.iter().any(|a| a == "--items") {
+        let threads = thread::available_parallelism().map_or(1, |n| n.get());
+        let paths = analyse(&with_worry::<Residues>(&input)?, 10000, &NoRelief, threads)?;
+        for path in &paths {
+            println!(
+                "Item {} of monkey {} is thrown {} times, inspections per monkey: {:?}",
+                path.origin.1,
+                path.origin.0,
+                path.throws(),
+                path.inspected
+            );
+        }
+        println!(
+            "Monkey business from the item paths: {}",
+            monkey_business(total_inspections(&paths, input.len()))
+        );
+        if let Some(most) = paths.iter().max_by_key(|p| p.throws()) {
+            println!(
+                "Item {} of monkey {} is thrown most",
+                most.origin.1, most.origin.0
+            );
+        }
+    }
+
+    if let Some(rounds) = args.iter().find_map(|a| a.strip_prefix("--rounds=")) {
+        let inspected = extrapolate(&with_worry::<Residues>(&input)?, rounds.parse()?, &NoRelief)?;
+        println!(
+            "Monkey business after {rounds} rounds: {}",
+            monkey_business(inspected)
+        );
+    }
     Ok(())
 }
 
@@ -247,17 +1030,190 @@ Monkey 3:
         assert_eq!(input[2].items[1], 60);
     }
 
+    #[test]
+    fn test_expr() {
+        let expr: Expr = "(old + 3) * old - 1".parse().unwrap();
+        assert_eq!(expr.eval(&2i64), Ok(9));
+
+        assert_eq!("old + 2 * old".parse::<Expr>().unwrap().eval(&3i64), Ok(9));
+        assert_eq!("old - 1 - 1".parse::<Expr>().unwrap().eval(&3i64), Ok(1));
+        assert_eq!(
+            "2 * (old - (1 + 1))".parse::<Expr>().unwrap().eval(&5i64),
+            Ok(6)
+        );
+
+        assert!("old +".parse::<Expr>().is_err());
+        assert!("(old * 2".parse::<Expr>().is_err());
+        assert!("old * 2)".parse::<Expr>().is_err());
+        assert!("old / 2".parse::<Expr>().is_err());
+    }
+
+    #[test]
+    fn test_variant_operation() {
+        let input = parse(&TEST.replace("old * 19", "(old + 3) * 19 - 1")).unwrap();
+
+        assert_eq!(input[0].op.eval(&79i64), Ok(1557));
+        assert_eq!(part_one(input.clone(), 20), Ok(10197));
+    }
+
     #[test]
     fn test_part_one() {
         let input = parse(TEST).unwrap();
 
-        assert_eq!(part_one(input.clone(), 20), 10605);
+        assert_eq!(part_one(input.clone(), 20), Ok(10605));
+        assert_eq!(part_one(with_worry::<u128>(&input).unwrap(), 20), Ok(10605));
     }
 
     #[test]
     fn test_part_two() {
         let input = parse(TEST).unwrap();
 
-        assert_eq!(part_two(input.clone(), 10000), 2713310158);
+        assert_eq!(part_two(input.clone(), 10000), Ok(2713310158));
+        assert_eq!(
+            part_two(with_worry::<Residues>(&input).unwrap(), 10000),
+            Ok(2713310158)
+        );
+    }
+
+    #[test]
+    fn test_overflow() {
+        let input = parse(TEST).unwrap();
+        let mut monkey = input[0].clone();
+        monkey.items = VecDeque::from([i64::MAX / 2]);
+        assert_eq!(monkey.inspect(&DivideBy(3)), Err(MyError::Overflow));
+
+        let mut monkey = with_worry::<u128>(&input).unwrap()[0].clone();
+        monkey.items = VecDeque::from([i64::MAX as u128 / 2]);
+        assert!(monkey.inspect(&DivideBy(3)).is_ok());
+
+        let mut monkey = with_worry::<Residues>(&input).unwrap()[0].clone();
+        assert_eq!(
+            monkey.inspect(&DivideBy(3)),
+            Err(MyError::Unsupported("division of residues"))
+        );
+    }
+
+    #[test]
+    fn test_negative_worry() {
+        let mut monkey: Monkey = "Monkey 0:
+  Starting items: 95
+  Operation: new = old - 100
+  Test: divisible by 2
+    If true: throw to monkey 1
+    If false: throw to monkey 1"
+            .parse()
+            .unwrap();
+        let inspection = monkey.inspect(&DivideBy(3)).unwrap().unwrap();
+        assert_eq!((inspection.new, inspection.relieved), (-5, -2));
+        assert!(inspection.divisible);
+    }
+
+    #[test]
+    fn test_relief() {
+        let input = parse(TEST).unwrap();
+        assert_eq!(ModuloLcm::of(&input).unwrap().0, 23 * 19 * 13 * 17);
+
+        let input = parse(&TEST.replace("by 19", "by 46")).unwrap();
+        assert_eq!(ModuloLcm::of(&input).unwrap().0, 46 * 13 * 17);
+
+        let mut monkeys = with_worry::<Residues>(&input).unwrap();
+        simulate(&mut monkeys, 20, &NoRelief).unwrap();
+        let mut modulo = input.clone();
+        simulate(&mut modulo, 20, &ModuloLcm::of(&input).unwrap()).unwrap();
+        assert_eq!(
+            monkeys.iter().map(|m| m.inspected).collect::<Vec<_>>(),
+            modulo.iter().map(|m| m.inspected).collect::<Vec<_>>()
+        );
+
+        let mut custom = input.clone();
+        simulate(&mut custom, 20, &|wl: i64| wl.div(3)).unwrap();
+        assert_eq!(
+            monkey_business(custom.iter().map(|m| m.inspected)),
+            part_one(input, 20).unwrap()
+        );
+    }
+
+    #[test]
+    fn test_trace() {
+        let mut input = parse(TEST).unwrap();
+        let mut trace = String::new();
+        simulate_traced(&mut input, 1, &DivideBy(3), Some(&mut trace)).unwrap();
+
+        assert!(trace.starts_with(
+            "Monkey 0:
+  Monkey inspects an item with a worry level of 79.
+    Worry level is multiplied by 19 to 1501.
+    Monkey gets bored with item. Worry level is divided by 3 to 500.
+    Current worry level is not divisible by 23.
+    Item with worry level 500 is thrown to monkey 3.
+"
+        ));
+        assert!(trace.contains(
+            "Monkey 2:
+  Monkey inspects an item with a worry level of 79.
+    Worry level is multiplied by itself to 6241.
+    Monkey gets bored with item. Worry level is divided by 3 to 2080.
+    Current worry level is divisible by 13.
+    Item with worry level 2080 is thrown to monkey 1.
+"
+        ));
+        assert!(trace.ends_with(
+            "After round 1, the monkeys are holding items with these worry levels:
+Monkey 0: 20, 23, 27, 26
+Monkey 1: 2080, 25, 167, 207, 401, 1046
+Monkey 2: 
+Monkey 3: 
+
+"
+        ));
+    }
+
+    #[test]
+    fn test_extrapolate() {
+        let input = parse(TEST).unwrap();
+        let residues = with_worry::<Residues>(&input).unwrap();
+        let relief = ModuloLcm::of(&input).unwrap();
+
+        assert_eq!(extrapolate(&input, 0, &relief).unwrap(), vec![0; 4]);
+        for rounds in [1, 20, 137, 1000] {
+            let mut monkeys = residues.clone();
+            simulate(&mut monkeys, rounds, &NoRelief).unwrap();
+            let simulated: Vec<usize> = monkeys.iter().map(|m| m.inspected).collect();
+
+            assert_eq!(
+                extrapolate(&residues, rounds, &NoRelief).unwrap(),
+                simulated
+            );
+            assert_eq!(extrapolate(&input, rounds, &relief).unwrap(), simulated);
+        }
+
+        let inspected = extrapolate(&residues, 10000, &NoRelief).unwrap();
+        assert_eq!(inspected, vec![52166, 47830, 1938, 52013]);
+        assert_eq!(monkey_business(inspected), 2713310158);
+    }
+
+    #[test]
+    fn test_analyse() {
+        let input = parse(TEST).unwrap();
+
+        let paths = analyse(&input, 2, &DivideBy(3), 3).unwrap();
+        assert_eq!(paths.len(), 10);
+        assert_eq!(paths[0].origin, (0, 0));
+        assert_eq!(paths[0].route, vec![0, 3, 1, 2, 3]);
+        assert_eq!(paths[0].inspected, vec![1, 1, 1, 2]);
+
+        let paths = analyse(&input, 20, &DivideBy(3), 4).unwrap();
+        assert_eq!(
+            monkey_business(total_inspections(&paths, input.len())),
+            10605
+        );
+
+        let residues = with_worry::<Residues>(&input).unwrap();
+        let paths = analyse(&residues, 10000, &NoRelief, 8).unwrap();
+        assert_eq!(
+            total_inspections(&paths, input.len()),
+            vec![52166, 47830, 1938, 52013]
+        );
+        assert_eq!(analyse(&residues, 10000, &NoRelief, 1).unwrap(), paths);
     }
 }
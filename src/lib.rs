@@ -1,6 +1,8 @@
 use curl::easy::{Easy, HttpVersion};
 use std::fs;
 
+pub mod ocr;
+
 pub fn get_input(year: &str, day: &str) -> String {
     let mut input = String::new();
     let mut easy = Easy::new();
@@ -0,0 +1,210 @@
+//! Reading the letters Advent of Code draws with lit pixels, like the CRT of
+//! day 10.
+use lazy_static::lazy_static;
+use std::collections::HashMap;
+use std::error::Error;
+use std::fmt;
+
+const FONT_6: &str = "\
+.##..###...##..####.####..##..#..#.###...##.#..#.#.....##..###..###...###.#..#.#...#.####
+#..#.#..#.#..#.#....#....#..#.#..#..#.....#.#.#..#....#..#.#..#.#..#.#....#..#.#...#....#
+#..#.###..#....###..###..#....####..#.....#.##...#....#..#.#..#.#..#.#....#..#..#.#....#.
+####.#..#.#....#....#....#.##.#..#..#.....#.#.#..#....#..#.###..###...##..#..#...#....#..
+#..#.#..#.#..#.#....#....#..#.#..#..#..#..#.#.#..#....#..#.#....#.#.....#.#..#...#...#...
+#..#.###...##..####.#.....###.#..#.###..##..#..#.####..##..#....#..#.###...##....#...####";
+const LETTERS_6: &str = "ABCEFGHIJKLOPRSUYZ";
+
+const FONT_10: &str = "\
+..##...#####...####..######.######..####..#....#....###.#....#.#.......#....#.#####..#####..#....#.######
+.#..#..#....#.#....#.#......#......#....#.#....#.....#..#...#..#.......##...#.#....#.#....#.#....#......#
+#....#.#....#.#......#......#......#......#....#.....#..#..#...#.......##...#.#....#.#....#..#..#.......#
+#....#.#....#.#......#......#......#......#....#.....#..#.#....#.......#.#..#.#....#.#....#..#..#......#.
+#....#.#####..#......#####..#####..#......######.....#..##.....#.......#.#..#.#####..#####....##......#..
+######.#....#.#......#......#......#..###.#....#.....#..##.....#.......#..#.#.#......#..#.....##.....#...
+#....#.#....#.#......#......#......#....#.#....#.....#..#.#....#.......#..#.#.#......#...#...#..#...#....
+#....#.#....#.#......#......#......#....#.#....#.#...#..#..#...#.......#...##.#......#...#...#..#..#.....
+#....#.#....#.#....#.#......#......#...##.#....#.#...#..#...#..#.......#...##.#......#....#.#....#.#.....
+#....#.#####...####..######.#.......###.#.#....#..###...#....#.######..#....#.#......#....#.#....#.######";
+const LETTERS_10: &str = "ABCEFGHJKLNPRXZ";
+
+lazy_static! {
+    static ref GLYPHS: HashMap<String, char> = {
+        let mut glyphs = HashMap::new();
+        for (font, letters) in [(FONT_6, LETTERS_6), (FONT_10, LETTERS_10)] {
+            let rows: Vec<&str> = font.lines().collect();
+            for (glyph, letter) in split_glyphs(&rows).into_iter().zip(letters.chars()) {
+                glyphs.insert(glyph, letter);
+            }
+        }
+        glyphs
+    };
+}
+
+#[derive(Debug, PartialEq)]
+pub enum OcrError {
+    /// Only fonts six or ten pixels high are known.
+    UnknownHeight(usize),
+    /// The rows of the screen have different lengths.
+    Ragged,
+    /// The glyphs without a letter, as their column on the screen and their
+    /// drawing.
+    UnknownGlyphs(Vec<(usize, String)>),
+    /// A letter the font has no glyph for.
+    UnknownLetter(char),
+}
+
+impl fmt::Display for OcrError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            OcrError::UnknownHeight(h) => write!(f, "no font is {h} pixels high"),
+            OcrError::Ragged => write!(f, "rows of the screen differ in length"),
+            OcrError::UnknownGlyphs(glyphs) => {
+                writeln!(f, "unknown glyphs:")?;
+                for (column, glyph) in glyphs {
+                    writeln!(f, "at column {column}:\n{glyph}")?;
+                }
+                Ok(())
+            }
+            OcrError::UnknownLetter(c) => write!(f, "no glyph for '{c}'"),
+        }
+    }
+}
+
+impl Error for OcrError {}
+
+fn is_lit(c: char) -> bool {
+    c == '#'
+}
+
+/// Cuts the screen at the columns without lit pixels, returning every glyph
+/// with the column it starts at.
+fn split_glyphs_at(rows: &[&str]) -> Vec<(usize, String)> {
+    let pixels: Vec<Vec<char>> = rows.iter().map(|r| r.chars().collect()).collect();
+    let width = pixels.first().map_or(0, |r| r.len());
+    let blank = |c: usize| pixels.iter().all(|r| !is_lit(r[c]));
+
+    let mut glyphs = Vec::new();
+    let mut c = 0;
+    while c < width {
+        if blank(c) {
+            c += 1;
+            continue;
+        }
+        let start = c;
+        while c < width && !blank(c) {
+            c += 1;
+        }
+        let glyph: Vec<String> = pixels
+            .iter()
+            .map(|r| {
+                r[start..c]
+                    .iter()
+                    .map(|&p| if is_lit(p) { '#' } else { '.' })
+                    .collect()
+            })
+            .collect();
+        glyphs.push((start, glyph.join("\n")));
+    }
+    glyphs
+}
+
+fn split_glyphs(rows: &[&str]) -> Vec<String> {
+    split_glyphs_at(rows).into_iter().map(|(_, g)| g).collect()
+}
+
+/// Reads the letters on `screen`, rows separated by newlines with `#` for a
+/// lit pixel and anything else for a dark one.
+pub fn recognize(screen: &str) -> Result<String, OcrError> {
+    let rows: Vec<&str> = screen.lines().filter(|l| !l.is_empty()).collect();
+    if rows.len() != 6 && rows.len() != 10 {
+        return Err(OcrError::UnknownHeight(rows.len()));
+    }
+    if rows
+        .iter()
+        .any(|r| r.chars().count() != rows[0].chars().count())
+    {
+        return Err(OcrError::Ragged);
+    }
+
+    let mut letters = String::new();
+    let mut unknown = Vec::new();
+    for (column, glyph) in split_glyphs_at(&rows) {
+        match GLYPHS.get(&glyph) {
+            Some(&c) => letters.push(c),
+            None => unknown.push((column, glyph)),
+        }
+    }
+
+    match unknown.is_empty() {
+        true => Ok(letters),
+        false => Err(OcrError::UnknownGlyphs(unknown)),
+    }
+}
+
+/// Draws `letters` in the six pixel high font, every glyph followed by a dark
+/// column like on the CRT of day 10.
+pub fn render(letters: &str) -> Result<String, OcrError> {
+    let glyphs: HashMap<char, &String> = GLYPHS
+        .iter()
+        .filter(|(g, _)| g.lines().count() == 6)
+        .map(|(g, &c)| (c, g))
+        .collect();
+
+    let mut rows = vec![String::new(); 6];
+    for c in letters.chars() {
+        let glyph = glyphs.get(&c).ok_or(OcrError::UnknownLetter(c))?;
+        for (row, line) in rows.iter_mut().zip(glyph.lines()) {
+            row.push_str(line);
+            row.push('.');
+        }
+    }
+    Ok(rows.iter().map(|r| format!("{r}\n")).collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_small_font() {
+        let screen = "\
+####.#..#.####.####.####.#..#..##..####.
+#....#..#....#.#.......#.#..#.#..#....#.
+###..####...#..###....#..####.#......#..
+#....#..#..#...#.....#...#..#.#.....#...
+#....#..#.#....#....#....#..#.#..#.#....
+####.#..#.####.#....####.#..#..##..####.
+";
+        assert_eq!(recognize(screen), Ok(String::from("EHZFZHCZ")));
+        assert_eq!(render("EHZFZHCZ").as_deref(), Ok(screen));
+        assert_eq!(recognize(FONT_6), Ok(String::from(LETTERS_6)));
+    }
+
+    #[test]
+    fn test_large_font() {
+        assert_eq!(recognize(FONT_10), Ok(String::from(LETTERS_10)));
+    }
+
+    #[test]
+    fn test_errors() {
+        assert_eq!(render("AQ"), Err(OcrError::UnknownLetter('Q')));
+
+        let screen = "\
+#..#.#...
+#..#.##..
+####.#.#.
+#..#.#..#
+#..#.#...
+#..#.#...
+";
+        assert_eq!(
+            recognize(screen),
+            Err(OcrError::UnknownGlyphs(vec![(
+                5,
+                String::from("#...\n##..\n#.#.\n#..#\n#...\n#...")
+            )]))
+        );
+        assert_eq!(recognize("#.\n.#"), Err(OcrError::UnknownHeight(2)));
+        assert_eq!(recognize("#\n#\n#\n##\n#\n#"), Err(OcrError::Ragged));
+    }
+}